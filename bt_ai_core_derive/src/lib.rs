@@ -0,0 +1,264 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parenthesized, parse_macro_input, punctuated::Punctuated, Attribute, Data, DeriveInput, Expr, ExprLit, Fields, Lit, LitStr, Meta, Token};
+
+///Derive `AiTool` and `ToolParamSchema` for a struct of tool arguments.
+///
+///The struct doc comment is the tool description and each field doc comment the parameter description.
+///`Option<T>` and `#[serde(default)]` fields are optional parameters. Attributes:
+///- `#[ai_tool(name = "tool_name")]` on the struct. Default: the struct name in snake case.
+///- `#[ai_tool(rename_all = "camelCase")]` on the struct and `#[ai_tool(rename = "param_name")]` on a field.
+///  The parameter names are always the names serde parses (`#[serde(rename)]`, then `#[serde(rename_all)]`),
+///  so these are a compile error when they give a different name.
+///- `#[ai_tool(enum_values("+", "-"))]` on a field to restrict its values.
+///
+///Fields with `#[serde(skip)]` or `#[serde(skip_deserializing)]` are not parameters.
+///`#[serde(flatten)]` fields are not supported.
+#[proc_macro_derive(AiTool, attributes(ai_tool))]
+pub fn derive_ai_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn get_doc(attrs: &[Attribute]) -> String {
+    let lines: Vec<String> = attrs.iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Some(s.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|l| !l.is_empty())
+        .collect();
+    lines.join(" ")
+}
+
+///`HTTPRequest` -> `http_request`, `DoBasicMath` -> `do_basic_math`
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit());
+            let acronym_end = i > 0 && chars[i - 1].is_uppercase() && chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev_lower || acronym_end {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(*c);
+        }
+    }
+    out
+}
+
+///Name of a snake case field with a serde `rename_all` rule applied
+fn apply_rename_all(rule: &str, field: &str) -> Option<String> {
+    let capitalize = |w: &str| {
+        let mut c = w.chars();
+        c.next().map(|f| f.to_uppercase().chain(c).collect::<String>()).unwrap_or_default()
+    };
+    Some(match rule {
+        "lowercase" | "snake_case" => field.to_owned(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_uppercase(),
+        "PascalCase" => field.split('_').map(capitalize).collect(),
+        "camelCase" => field.split('_').enumerate().map(|(i, w)| if i == 0 { w.to_owned() } else { capitalize(w) }).collect(),
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.replace('_', "-").to_uppercase(),
+        _ => return None,
+    })
+}
+
+#[derive(Default)]
+struct AiToolAttrs {
+    name: Option<String>,
+    rename: Option<LitStr>,
+    rename_all: Option<LitStr>,
+    enum_values: Option<Vec<Lit>>,
+}
+
+///The serde attributes that change the parameters of the tool
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<LitStr>,
+    default: bool,
+    skip: bool,
+    flatten: bool,
+}
+
+fn get_attrs(attrs: &[Attribute]) -> syn::Result<AiToolAttrs> {
+    let mut out = AiToolAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("ai_tool")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                out.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("rename") {
+                out.rename = Some(meta.value()?.parse::<LitStr>()?);
+            } else if meta.path.is_ident("rename_all") {
+                out.rename_all = Some(meta.value()?.parse::<LitStr>()?);
+            } else if meta.path.is_ident("enum_values") {
+                let content;
+                parenthesized!(content in meta.input);
+                let values = Punctuated::<Lit, Token![,]>::parse_terminated(&content)?;
+                out.enum_values = Some(values.into_iter().collect());
+            } else {
+                return Err(meta.error("unsupported ai_tool attribute. Use name, rename, rename_all or enum_values"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(out)
+}
+
+fn get_serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut out = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") || meta.path.is_ident("rename_all") {
+                //`rename = "x"` or `rename(serialize = "x", deserialize = "y")`: the arguments are deserialized
+                let value = if meta.input.peek(Token![=]) {
+                    Some(meta.value()?.parse::<LitStr>()?)
+                } else {
+                    let mut deserialize = None;
+                    meta.parse_nested_meta(|inner| {
+                        let v = inner.value()?.parse::<LitStr>()?;
+                        if inner.path.is_ident("deserialize") {
+                            deserialize = Some(v);
+                        }
+                        Ok(())
+                    })?;
+                    deserialize
+                };
+                if meta.path.is_ident("rename") {
+                    out.rename = value.map(|v| v.value()).or(out.rename.take());
+                } else {
+                    out.rename_all = value.or(out.rename_all.take());
+                }
+            } else {
+                if meta.path.is_ident("default") {
+                    out.default = true;
+                } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                    out.skip = true;
+                } else if meta.path.is_ident("flatten") {
+                    out.flatten = true;
+                }
+                //Other serde attributes do not change the schema: skip their value
+                if meta.input.peek(Token![=]) {
+                    meta.value()?.parse::<Expr>()?;
+                } else if meta.input.peek(syn::token::Paren) {
+                    let content;
+                    parenthesized!(content in meta.input);
+                    content.parse::<TokenStream2>()?;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(out)
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "AiTool does not support generic structs"));
+    }
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(ident, "AiTool can only be derived for structs"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(ident, "AiTool requires a struct with named fields"));
+    };
+
+    let description = get_doc(&input.attrs);
+    if description.is_empty() {
+        return Err(syn::Error::new_spanned(ident, "AiTool requires a doc comment on the struct to use as description"));
+    }
+    let struct_attrs = get_attrs(&input.attrs)?;
+    let struct_serde = get_serde_attrs(&input.attrs)?;
+    let tool_name = struct_attrs.name.unwrap_or_else(|| to_snake_case(&ident.to_string()));
+    for rule in struct_attrs.rename_all.iter().chain(struct_serde.rename_all.iter()) {
+        if apply_rename_all(&rule.value(), "").is_none() {
+            return Err(syn::Error::new_spanned(rule, "unknown rename_all rule"));
+        }
+    }
+
+    let mut param_stmts = Vec::new();
+    for f in &fields.named {
+        let f_attrs = get_attrs(&f.attrs)?;
+        let f_serde = get_serde_attrs(&f.attrs)?;
+        if f_serde.skip {
+            continue;
+        }
+        if f_serde.flatten {
+            return Err(syn::Error::new_spanned(f, "AiTool does not support #[serde(flatten)] fields"));
+        }
+        let field = f.ident.as_ref().map(|i| i.to_string().trim_start_matches("r#").to_owned()).unwrap_or_default();
+        //The arguments are parsed by serde: the parameter is named as serde expects it
+        let renamed = |rule: &Option<LitStr>| rule.as_ref().and_then(|r| apply_rename_all(&r.value(), &field));
+        let f_name = f_serde.rename.clone().or_else(|| renamed(&struct_serde.rename_all)).unwrap_or_else(|| field.clone());
+        let ai_name = f_attrs.rename.as_ref().map(|r| r.value()).or(f_serde.rename)
+            .or_else(|| renamed(&struct_attrs.rename_all).or_else(|| renamed(&struct_serde.rename_all)))
+            .unwrap_or(field);
+        if ai_name != f_name {
+            let msg = format!("ai_tool name `{}` does not match the serde name `{}` used to parse the arguments", ai_name, f_name);
+            return Err(match &f_attrs.rename {
+                Some(r) => syn::Error::new_spanned(r, msg),
+                None => syn::Error::new_spanned(f, msg),
+            });
+        }
+        let optional = struct_serde.default || f_serde.default;
+        let f_ty = &f.ty;
+        let f_doc = get_doc(&f.attrs);
+        let set_doc = if f_doc.is_empty() { quote!() } else { quote!(p.set_description(#f_doc);) };
+        let set_enum = match f_attrs.enum_values {
+            Some(values) => quote!(p.set_enum(vec![#(::bt_ai_core::ai_tool_schema::__private::Value::from(#values)),*]);),
+            None => quote!(),
+        };
+        param_stmts.push(quote! {
+            {
+                let mut p = <#f_ty as ::bt_ai_core::ai_tool_schema::ToolParamSchema>::get_param_schema();
+                #set_doc
+                #set_enum
+                if !#optional && <#f_ty as ::bt_ai_core::ai_tool_schema::ToolParamSchema>::is_required() {
+                    required.push(#f_name.to_owned());
+                }
+                properties.insert(#f_name.to_owned(), p);
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl ::bt_ai_core::ai_tool_schema::ToolParamSchema for #ident {
+            fn get_param_schema() -> ::bt_ai_core::ai_tools::ToolParamProperty {
+                let mut properties = ::bt_ai_core::ai_tool_schema::__private::HashMap::new();
+                let mut required: Vec<String> = Vec::new();
+                #(#param_stmts)*
+                let mut obj = ::bt_ai_core::ai_tools::ToolParamProperty::new("object");
+                obj.set_description(#description);
+                obj.set_properties(properties, required);
+                obj
+            }
+        }
+
+        impl ::bt_ai_core::ai_tool_schema::AiTool for #ident {
+            fn get_tool() -> ::bt_ai_core::ai_tools::Tool {
+                let schema = <Self as ::bt_ai_core::ai_tool_schema::ToolParamSchema>::get_param_schema();
+                ::bt_ai_core::ai_tools::Tool::new(
+                    #tool_name,
+                    #description,
+                    ::bt_ai_core::ai_tools::FunctionParameters::new(
+                        schema.get_properties().cloned().unwrap_or_default(),
+                        schema.get_required().cloned().unwrap_or_default(),
+                    ),
+                )
+            }
+        }
+    })
+}
@@ -2,6 +2,8 @@ use bt_logger::{log_error, log_trace};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    ai_config::WireFormat,
+    ai_openai_helper::{get_ai_chat_response_from_openai, get_openai_chat_request_json},
     ai_tools::Tool,
     message::{Message, MessageRole},
 };
@@ -63,6 +65,22 @@ pub fn get_chat_request_json(ai_request: &AIChatRequest) -> String{
     }
 }
 
+///JSON body of the request in the wire format of the target platform
+pub fn get_chat_request_json_by_format(ai_request: &AIChatRequest, wire_format: &WireFormat) -> String{
+    match wire_format {
+        WireFormat::OLLAMA => get_chat_request_json(ai_request),
+        WireFormat::OPENAI => get_openai_chat_request_json(ai_request),
+    }
+}
+
+///Decode a (non streamed) chat response body in the wire format of the source platform
+pub fn get_chat_response_by_format(body: &str, wire_format: &WireFormat) -> Result<AIChatResponse, serde_json::Error>{
+    match wire_format {
+        WireFormat::OLLAMA => serde_json::from_str(body),
+        WireFormat::OPENAI => get_ai_chat_response_from_openai(body),
+    }
+}
+
 pub fn get_chat_ai_chat_request( ai_model: &String, role: MessageRole, message: &String, context: Vec<Message>, system: Option<String>, tool_list: Option<Vec<Tool>>, 
                                 current_date: &str, current_time: &str, stream_ans: bool ) -> AIChatRequest {
     log_trace!( "model_chat", "Ready to start chat role {:?}: {}", &role, &message );
@@ -93,7 +111,7 @@ pub fn get_chat_ai_chat_request( ai_model: &String, role: MessageRole, message:
 mod tests_ai_config {
     use bt_logger::{LogLevel, LogTarget, build_logger};
 
-    use crate::{ai_chat_helper::{get_chat_ai_chat_request, get_chat_request_json}, message::MessageRole};
+    use crate::{ai_chat_helper::{get_chat_ai_chat_request, get_chat_request_json, get_chat_request_json_by_format, get_chat_response_by_format}, ai_config::WireFormat, message::MessageRole};
 
     #[test]
     fn test_chat_req_success() {
@@ -120,4 +138,26 @@ mod tests_ai_config {
         println!("MSG: {}", &json_resp);
         assert_eq!(json_resp, json_a);
     }
+
+    #[test]
+    fn test_chat_req_by_format() {
+        build_logger("BACHUETECH", "BT.AI_CHAT_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let req = get_chat_ai_chat_request(&"llama3.1".to_string(), MessageRole::USER, &"The prompt".to_string(), Vec::new(), None, None, "03/27/2025", "6:45 PM", true);
+        assert_eq!(get_chat_request_json_by_format(&req, &WireFormat::OLLAMA), get_chat_request_json(&req));
+        assert_eq!(get_chat_request_json_by_format(&req, &WireFormat::OPENAI),
+            "{\"model\":\"llama3.1\",\"messages\":[{\"role\":\"user\",\"content\":\"The prompt\"}],\"stream\":true,\"stream_options\":{\"include_usage\":true}}");
+    }
+
+    #[test]
+    fn test_chat_resp_by_format() {
+        build_logger("BACHUETECH", "BT.AI_CHAT_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let ollama = "{\"model\":\"llama3.1\",\"created_at\":\"2025-03-27T18:45:00Z\",\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"},\"done\":true,\"eval_count\":3}";
+        let openai = "{\"model\":\"llama3.1\",\"created\":1743101100,\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":3,\"total_tokens\":8}}";
+        let r1 = get_chat_response_by_format(ollama, &WireFormat::OLLAMA).unwrap();
+        let r2 = get_chat_response_by_format(openai, &WireFormat::OPENAI).unwrap();
+        assert_eq!(r1.message.get_content(), r2.message.get_content());
+        assert_eq!(r1.eval_count, r2.eval_count);
+        assert!(r2.done);
+        assert!(get_chat_response_by_format(openai, &WireFormat::OLLAMA).is_err());
+    }
 }
@@ -0,0 +1,449 @@
+use std::{collections::HashMap, error::Error, time::{SystemTime, UNIX_EPOCH}};
+
+use bt_http_utils::{HttpClient, HttpResponse};
+use bt_logger::{get_error, log_trace, log_verbose, log_warning};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    ai_context::trim_context,
+    ai_chat_helper::{get_chat_ai_chat_request, get_chat_request_json_by_format, get_chat_response_by_format, AIChatRequest, AIChatResponse},
+    ai_config::{AIConfig, InteractionType, WireFormat},
+    ai_endpoint_helper::{
+        get_embed_request, get_embed_request_json_by_format, get_embed_response_by_format, get_request_json, AIEmbedResponse, AIPullRequest, AIPullStatus,
+        AIRunningModels, AIShowRequest, AIShowResponse, AIVersionResponse, EmbedInput,
+    },
+    ai_stream_helper::process_stream_by_format,
+    ai_tools::AIToolManager,
+    message::{Message, MessageRole},
+};
+
+///Async chat client. Resolves URL, model name, system message, tools and wire format of a platform from the AI configuration.
+pub struct AIClient {
+    http_client: HttpClient,
+    tool_manager: AIToolManager,
+}
+
+impl AIClient {
+    pub fn new(run_environment: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new_with_tool_manager(AIToolManager::new(run_environment)?))
+    }
+
+    pub fn new_with_tool_manager(tool_manager: AIToolManager) -> Self {
+        Self {
+            http_client: HttpClient::new(false, false),
+            tool_manager,
+        }
+    }
+
+    pub fn get_ai_config(&self) -> &AIConfig {
+        self.tool_manager.get_ai_config()
+    }
+
+    pub fn get_tool_manager(&self) -> &AIToolManager {
+        &self.tool_manager
+    }
+
+    ///Build the chat request for a model of a platform: configured model name, system message and tools plus `context` and the new message.
+    ///An empty platform name selects the `default_platform` of the configuration.
+    ///The oldest `context` messages beyond the `ctx_max` of the platform are dropped (see `trim_context`).
+    pub fn build_chat_request(&self, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>, stream_ans: bool) -> AIChatRequest {
+        self.build_trimmed_chat_request(platform_name, model_id, role, message, context, stream_ans).0
+    }
+
+    ///Same as `build_chat_request`, also returning the number of `context` messages dropped to fit `ctx_max`
+    pub fn build_trimmed_chat_request(&self, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>, stream_ans: bool) -> (AIChatRequest, usize) {
+        let ai_config = self.get_ai_config();
+        let platform_name = ai_config.resolve_platform(platform_name).unwrap_or(platform_name);
+        let trimmed = trim_context(context, ai_config.get_max_ctx_size(platform_name));
+        let (current_date, current_time) = get_current_date_time();
+        let ai_request = get_chat_ai_chat_request(
+            &ai_config.get_model(platform_name, model_id, &"".to_owned()),
+            role,
+            message,
+            trimmed.messages,
+            ai_config.get_system_msg(platform_name, model_id),
+            self.tool_manager.get_tools(platform_name, model_id),
+            &current_date,
+            &current_time,
+            stream_ans,
+        );
+        (ai_request, trimmed.dropped)
+    }
+
+    ///Send a chat message to a model and wait for the complete answer
+    pub async fn chat(&self, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>) -> Result<AIChatResponse, Box<dyn Error>> {
+        let ai_request = self.build_chat_request(platform_name, model_id, role, message, context, false);
+        self.send_chat_request(platform_name, &ai_request).await
+    }
+
+    ///Send a chat message to a model, stream the answer and return it once complete
+    pub async fn chat_stream(&self, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>) -> Result<AIChatResponse, Box<dyn Error>> {
+        let ai_request = self.build_chat_request(platform_name, model_id, role, message, context, true);
+        self.send_chat_request(platform_name, &ai_request).await
+    }
+
+    ///Send an already built request to a platform. `ai_request.stream` selects between a single response and a streamed one.
+    ///An unknown platform is an error (see `AIConfig::resolve_platform`).
+    pub async fn send_chat_request(&self, platform_name: &str, ai_request: &AIChatRequest) -> Result<AIChatResponse, Box<dyn Error>> {
+        let ai_config = self.get_ai_config();
+        let platform_name = ai_config.resolve_platform(platform_name)
+            .map_err(|e| get_error!("send_chat_request", "{}", e))?;
+        let wire_format = ai_config.get_wire_format(platform_name);
+        let url = ai_config.try_get_url(platform_name, InteractionType::Chat)?;
+        let body = get_chat_request_json_by_format(ai_request, &wire_format);
+        log_trace!("send_chat_request", "Sending request to {}: {}", &url, &body);
+
+        let mut header = get_json_header();
+        header.extend(ai_config.get_auth_headers(platform_name));
+
+        let http_resp: HttpResponse = if ai_request.stream {
+            let streamer = self.http_client.post_stream(&url, Some(header), &body).await
+                .map_err(|e| get_error!("send_chat_request", "Error sending chat request to {}. Error: {}", &url, e))?;
+            process_stream_by_format(streamer, &wire_format).await
+        } else {
+            self.http_client.post(&url, Some(header), &body).await
+                .map_err(|e| get_error!("send_chat_request", "Error sending chat request to {}. Error: {}", &url, e))?
+        };
+        log_verbose!("send_chat_request", "Response status {} from {}", http_resp.status_code, &url);
+
+        if !(200..300).contains(&http_resp.status_code) {
+            return Err(get_error!("send_chat_request", "Chat request to {} failed with status {}. Body: {}", &url, http_resp.status_code, &http_resp.body).into());
+        }
+
+        //Streamed responses were already converted into the Ollama (internal) shape by process_stream_by_format
+        let resp = if ai_request.stream {
+            serde_json::from_str(&http_resp.body)
+        } else {
+            get_chat_response_by_format(&http_resp.body, &wire_format)
+        };
+        resp.map_err(|e| get_error!("send_chat_request", "Invalid chat response from {}: {}. Error: {}", &url, &http_resp.body, e).into())
+    }
+
+    ///Embedding vectors of one or more texts, computed by the configured model of a platform
+    pub async fn embed(&self, platform_name: &str, model_id: &String, input: EmbedInput) -> Result<AIEmbedResponse, Box<dyn Error>> {
+        let (platform_name, wire_format) = self.resolve_platform(platform_name)?;
+        let request = get_embed_request(&self.get_ai_config().get_model(&platform_name, model_id, &"".to_owned()), input);
+        let body = self.send_api_request(&platform_name, InteractionType::Embeddings, Some(get_embed_request_json_by_format(&request, &wire_format))).await?;
+        get_embed_response_by_format(&body, &wire_format)
+            .map_err(|e| get_error!("embed", "Invalid embeddings response: {}. Error: {}", &body, e).into())
+    }
+
+    ///Details of the configured model of a platform (Ollama `show`)
+    pub async fn show_model(&self, platform_name: &str, model_id: &String) -> Result<AIShowResponse, Box<dyn Error>> {
+        let (platform_name, _) = self.resolve_platform(platform_name)?;
+        let request = AIShowRequest { model: self.get_ai_config().get_model(&platform_name, model_id, &"".to_owned()), verbose: None };
+        self.send_ollama_request(&platform_name, InteractionType::Show, Some(get_request_json(&request, &request.model))).await
+    }
+
+    ///Download the configured model of a platform and wait until it is done (Ollama `pull`)
+    pub async fn pull_model(&self, platform_name: &str, model_id: &String) -> Result<AIPullStatus, Box<dyn Error>> {
+        let (platform_name, _) = self.resolve_platform(platform_name)?;
+        let request = AIPullRequest { model: self.get_ai_config().get_model(&platform_name, model_id, &"".to_owned()), insecure: None, stream: false };
+        self.send_ollama_request(&platform_name, InteractionType::Pull, Some(get_request_json(&request, &request.model))).await
+    }
+
+    ///Models loaded in memory by a platform (Ollama `ps`)
+    pub async fn list_running_models(&self, platform_name: &str) -> Result<AIRunningModels, Box<dyn Error>> {
+        let (platform_name, _) = self.resolve_platform(platform_name)?;
+        self.send_ollama_request(&platform_name, InteractionType::Ps, None).await
+    }
+
+    ///Version of the server of a platform (Ollama `version`). Also a cheap availability probe.
+    pub async fn get_version(&self, platform_name: &str) -> Result<AIVersionResponse, Box<dyn Error>> {
+        let (platform_name, _) = self.resolve_platform(platform_name)?;
+        self.send_ollama_request(&platform_name, InteractionType::Version, None).await
+    }
+
+    fn resolve_platform(&self, platform_name: &str) -> Result<(String, WireFormat), Box<dyn Error>> {
+        let ai_config = self.get_ai_config();
+        let platform_name = ai_config.resolve_platform(platform_name)
+            .map_err(|e| get_error!("resolve_platform", "{}", e))?;
+        Ok((platform_name.clone(), ai_config.get_wire_format(platform_name)))
+    }
+
+    ///Send a request to an end point that only exists in the Ollama wire format and decode its response
+    async fn send_ollama_request<T: DeserializeOwned>(&self, platform_name: &str, int_type: InteractionType, body: Option<String>) -> Result<T, Box<dyn Error>> {
+        if self.get_ai_config().get_wire_format(&platform_name.to_owned()) != WireFormat::OLLAMA {
+            log_warning!("send_ollama_request", "Platform {} does not use the Ollama wire format. The {} end point may not exist", platform_name, int_type.as_str());
+        }
+        let resp_body = self.send_api_request(platform_name, int_type, body).await?;
+        serde_json::from_str(&resp_body)
+            .map_err(|e| get_error!("send_ollama_request", "Invalid {} response: {}. Error: {}", int_type.as_str(), &resp_body, e).into())
+    }
+
+    ///POST `body` (GET if None) to an end point of a platform and return the response body. Statuses other than 2xx are errors.
+    async fn send_api_request(&self, platform_name: &str, int_type: InteractionType, body: Option<String>) -> Result<String, Box<dyn Error>> {
+        let ai_config = self.get_ai_config();
+        let url = ai_config.try_get_url(platform_name, int_type)?;
+        let mut header = get_json_header();
+        header.extend(ai_config.get_auth_headers(&platform_name.to_owned()));
+        log_trace!("send_api_request", "Sending {} request to {}: {}", int_type.as_str(), &url, body.as_deref().unwrap_or_default());
+
+        let http_resp = match &body {
+            Some(b) => self.http_client.post(&url, Some(header), b).await,
+            None => self.http_client.get(&url, Some(header)).await,
+        }.map_err(|e| get_error!("send_api_request", "Error sending {} request to {}. Error: {}", int_type.as_str(), &url, e))?;
+        log_verbose!("send_api_request", "Response status {} from {}", http_resp.status_code, &url);
+
+        if !(200..300).contains(&http_resp.status_code) {
+            return Err(get_error!("send_api_request", "{} request to {} failed with status {}. Body: {}", int_type.as_str(), &url, http_resp.status_code, &http_resp.body).into());
+        }
+        Ok(http_resp.body)
+    }
+}
+
+fn get_json_header() -> HashMap<String, String> {
+    HashMap::from([("Content-Type".to_owned(), "application/json".to_owned())])
+}
+
+///Current UTC date (MM/DD/YYYY) and time (h:MM AM/PM UTC) used in the system message
+fn get_current_date_time() -> (String, String) {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format_date_time(secs)
+}
+
+fn format_date_time(unix_secs: u64) -> (String, String) {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+
+    //Civil date from days since epoch (H. Hinnant algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let hour = secs_of_day / 3_600;
+    let minute = (secs_of_day % 3_600) / 60;
+    let (h12, am_pm) = match hour {
+        0 => (12, "AM"),
+        1..=11 => (hour, "AM"),
+        12 => (12, "PM"),
+        _ => (hour - 12, "PM"),
+    };
+
+    (format!("{:02}/{:02}/{}", month, day, year), format!("{}:{:02} {} UTC", h12, minute, am_pm))
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+pub(crate) mod tests_ai_client {
+    use std::{io::{BufRead, BufReader, Read, Write}, net::TcpListener, sync::mpsc::{self, Receiver}, thread};
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use yaml_rust2::YamlLoader;
+
+    use crate::{ai_config::AIConfig, ai_endpoint_helper::EmbedInput, ai_tools::AIToolManager, message::{Message, MessageRole}};
+
+    use super::{format_date_time, AIClient};
+
+    ///Local mock HTTP server. Answers each request with the next body in `responses` and sends the received requests (headers and body) to the returned channel.
+    pub(crate) fn start_mock_server(responses: Vec<(u16, String)>) -> (u16, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (status, resp_body) in responses {
+                let Ok((mut stream, _)) = listener.accept() else { return };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    request.push_str(&line);
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((k, v)) = line.split_once(':') && k.trim().eq_ignore_ascii_case("content-length") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                tx.send(request).unwrap();
+                let resp = format!("HTTP/1.1 {} MOCK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, resp_body.len(), resp_body);
+                stream.write_all(resp.as_bytes()).unwrap();
+            }
+        });
+        (port, rx)
+    }
+
+    pub(crate) fn get_mock_config(port: u16, wire_format: &str) -> AIConfig {
+        let y = YamlLoader::load_from_str(&format!("
+name: BT_AI
+dev:
+  platform:
+    - name: MOCK
+      server:
+        host: 127.0.0.1
+        port: {}
+        secure: false
+      api:
+        ctx_max: 10
+        wire_format: {}
+      models:
+        - model_id: llama3.1
+          model: llama3.1:8b
+          system: You are an AI assistant.
+          tools: ALL
+        - model_id: default
+          model: llama3.3:70b
+          system: You are an AI assistant
+          tools: NONE
+", port, wire_format)).unwrap();
+        AIConfig::new_from_yaml(&y[0], "dev")
+    }
+
+    #[test]
+    fn test_format_date_time() {
+        assert_eq!(format_date_time(0), ("01/01/1970".to_owned(), "12:00 AM UTC".to_owned()));
+        assert_eq!(format_date_time(1_743_101_100), ("03/27/2025".to_owned(), "6:45 PM UTC".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_chat_success() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.1:8b\",\"created_at\":\"2025-03-27T18:45:00Z\",\"message\":{\"role\":\"assistant\",\"content\":\"Hello!\"},\"done\":true}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let resp = client.chat(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await.unwrap();
+        assert_eq!(resp.message.get_content(), "Hello!");
+
+        let req = rx.recv().unwrap();
+        assert!(req.contains("\"model\":\"llama3.1:8b\""));
+        assert!(req.contains("Your Are BT_AI. You are an AI assistant."));
+        assert!(req.contains("\"tools\":["));
+    }
+
+    #[tokio::test]
+    async fn test_chat_openai_success() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.3:70b\",\"created\":1743101100,\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"Hello!\"},\"finish_reason\":\"stop\"}]}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "openai")));
+        let resp = client.chat(&"MOCK".to_owned(), &"unknown".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await.unwrap();
+        assert_eq!(resp.message.get_content(), "Hello!");
+        assert!(resp.done);
+
+        let req = rx.recv().unwrap();
+        assert!(req.contains("\"model\":\"unknown\""));
+        assert!(!req.contains("\"tools\""));
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_success() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let chunks = "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":true,\"eval_count\":2}\n";
+        let (port, rx) = start_mock_server(vec![(200, chunks.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let resp = client.chat_stream(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await.unwrap();
+        assert_eq!(resp.message.get_content(), "Hello");
+        assert_eq!(resp.eval_count, Some(2));
+        assert!(rx.recv().unwrap().contains("\"stream\":true"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_http_error() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, _rx) = start_mock_server(vec![(404, "{\"error\":\"model not found\"}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let resp = client.chat(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await;
+        assert!(resp.unwrap_err().to_string().contains("404"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_auth_headers() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"Hello!\"},\"done\":true}".to_owned())]);
+        let y = YamlLoader::load_from_str(&format!("
+dev:
+  platform:
+    - name: MOCK
+      server: {{host: 127.0.0.1, port: {}, secure: false}}
+      api: {{ctx_max: 10}}
+      auth:
+        bearer_token: sk-mock-token
+        headers:
+          X-Org-Id: bachuetech
+", port)).unwrap();
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(AIConfig::new_from_yaml(&y[0], "dev")));
+        client.chat(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await.unwrap();
+
+        let req = rx.recv().unwrap().to_lowercase();
+        assert!(req.contains("authorization: bearer sk-mock-token"));
+        assert!(req.contains("x-org-id: bachuetech"));
+        assert!(req.contains("content-type: application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_unknown_platform() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"Hello!\"},\"done\":true}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let err = client.chat(&"MOKC".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), Vec::new()).await.unwrap_err();
+        assert!(err.to_string().contains("Platform MOKC is not defined. Did you mean MOCK?"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.1:8b\",\"embeddings\":[[0.5,0.25]]}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let resp = client.embed("MOCK", &"llama3.1".to_owned(), EmbedInput::Text("Hi".to_owned())).await.unwrap();
+        assert_eq!(resp.embeddings, vec![vec![0.5, 0.25]]);
+        let req = rx.recv().unwrap();
+        assert!(req.starts_with("POST /api/embed "));
+        assert!(req.ends_with("{\"model\":\"llama3.1:8b\",\"input\":\"Hi\"}"));
+
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.3:70b\",\"data\":[{\"index\":0,\"embedding\":[0.5]}]}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "openai")));
+        let resp = client.embed("MOCK", &"default".to_owned(), EmbedInput::Texts(vec!["Hi".to_owned()])).await.unwrap();
+        assert_eq!(resp.embeddings, vec![vec![0.5]]);
+        assert!(rx.recv().unwrap().starts_with("POST /v1/embeddings "));
+    }
+
+    #[tokio::test]
+    async fn test_model_endpoints() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![
+            (200, "{\"version\":\"0.6.2\"}".to_owned()),
+            (200, "{\"models\":[{\"name\":\"llama3.1:8b\",\"size\":6654289920}]}".to_owned()),
+            (200, "{\"modelfile\":\"FROM llama3.1\",\"capabilities\":[\"completion\",\"tools\"]}".to_owned()),
+            (200, "{\"status\":\"success\"}".to_owned()),
+            (500, "{\"error\":\"pull failed\"}".to_owned()),
+        ]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        assert_eq!(client.get_version("MOCK").await.unwrap().version, "0.6.2");
+        assert!(rx.recv().unwrap().starts_with("GET /api/version "));
+        assert_eq!(client.list_running_models("MOCK").await.unwrap().models[0].name, "llama3.1:8b");
+        assert!(rx.recv().unwrap().starts_with("GET /api/ps "));
+        assert_eq!(client.show_model("MOCK", &"llama3.1".to_owned()).await.unwrap().capabilities, vec!["completion", "tools"]);
+        let req = rx.recv().unwrap();
+        assert!(req.starts_with("POST /api/show ") && req.ends_with("{\"model\":\"llama3.1:8b\"}"));
+        assert_eq!(client.pull_model("MOCK", &"llama3.1".to_owned()).await.unwrap().status, "success");
+        assert!(rx.recv().unwrap().ends_with("{\"model\":\"llama3.1:8b\",\"stream\":false}"));
+        assert!(client.pull_model("MOCK", &"llama3.1".to_owned()).await.unwrap_err().to_string().contains("500"));
+        assert!(client.get_version("UNKNOWN").await.is_err());
+    }
+
+    #[test]
+    fn test_build_trimmed_chat_request() {
+        build_logger("BACHUETECH", "BT.AI_CLIENT", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(1, "ollama")));
+        let context: Vec<Message> = (0..12).map(|i| Message::new(if i % 2 == 0 { MessageRole::USER } else { MessageRole::ASSISTANT }, format!("m{}", i))).collect();
+        let (req, dropped) = client.build_trimmed_chat_request(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), context, false);
+        //ctx_max 10: system message, the last 10 context messages and the new prompt
+        assert_eq!(dropped, 2);
+        assert_eq!(req.messages.len(), 12);
+        assert_eq!(req.messages[0].get_role().clone(), MessageRole::SYSTEM);
+        assert_eq!(req.messages[1].get_content(), "m2");
+        assert_eq!(req.messages[11].get_content(), "Hi");
+    }
+}
@@ -0,0 +1,353 @@
+use std::{collections::BTreeMap, fmt};
+
+use bt_logger::{log_error, log_trace, log_warning};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{Map, Number, Value};
+use yaml_rust2::Yaml;
+
+use crate::{ai_config::SupportedFunctions, config_interpolation::SecretValues};
+
+///Typed definitions of the AI YML configuration file, as written in the file.
+///Every key is optional so missing keys can be reported; unknown keys are kept in `extra`.
+///Definitions that can hold secrets do not implement `Debug`.
+
+#[derive(Deserialize, Default)]
+pub(crate) struct EnvDef {
+    pub platform: Option<Vec<Value>>,
+    pub default_platform: Option<String>,
+    pub summarizer: Option<SummarizerDef>,
+    pub tool_groups: Option<BTreeMap<String, NameList>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct PlatformDef {
+    pub name: Option<String>,
+    pub base_url: Option<String>,
+    pub server: Option<ServerDef>,
+    pub api: Option<ApiDef>,
+    pub auth: Option<AuthDef>,
+    pub models: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ServerDef {
+    pub host: Option<String>,
+    pub port: Option<i64>,
+    pub secure: Option<bool>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ApiDef {
+    pub ctx_max: Option<i64>,
+    pub wire_format: Option<String>,
+    pub path: Option<String>,
+    pub chat: Option<String>,
+    pub generate: Option<String>,
+    pub models: Option<String>,
+    pub embeddings: Option<String>,
+    pub show: Option<String>,
+    pub pull: Option<String>,
+    pub ps: Option<String>,
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+///Authentication of the requests to a platform: `bearer_token` or `basic`, plus any custom `headers`
+#[derive(Deserialize, Default)]
+pub(crate) struct AuthDef {
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    pub bearer_token: Option<String>,
+    pub basic: Option<BasicAuthDef>,
+    #[serde(default, deserialize_with = "deserialize_scalar_map")]
+    pub headers: Option<BTreeMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct BasicAuthDef {
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    pub username: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    pub password: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ModelDef {
+    pub model_id: Option<String>,
+    pub model: Option<String>,
+    pub system: Option<String>,
+    pub tools: Option<SupportedFunctions>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+///Model that summarizes the conversation history dropped to fit the context
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct SummarizerDef {
+    pub platform: Option<String>,
+    pub model_id: Option<String>,
+    pub instructions: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+///Text of a string, number or boolean value. Credentials such as `username: 12345` are strings even if YAML types them.
+fn scalar_to_string<E: serde::de::Error>(value: Value) -> Result<String, E> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        other => Err(E::custom(format!("expected a string, found {}", other))),
+    }
+}
+
+fn deserialize_scalar<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Option::<Value>::deserialize(deserializer)?.filter(|v| !v.is_null()).map(scalar_to_string).transpose()
+}
+
+fn deserialize_scalar_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BTreeMap<String, String>>, D::Error> {
+    let Some(map) = Option::<BTreeMap<String, Value>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    map.into_iter().map(|(k, v)| scalar_to_string(v).map(|v| (k, v))).collect::<Result<_, _>>().map(Some)
+}
+
+///A YAML list of names or a comma-separated string
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum NameList {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl NameList {
+    pub fn get_names(self) -> Vec<String> {
+        match self {
+            NameList::Text(s) => s.split(',').map(|n| n.trim().to_owned()).filter(|n| !n.is_empty()).collect(),
+            NameList::List(l) => l.into_iter().map(|n| n.trim().to_owned()).collect(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SupportedFunctions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Filter {
+            include: Option<SupportedFunctions>,
+            exclude: Option<NameList>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            List(Vec<String>),
+            Filter(Filter),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Text(s) => SupportedFunctions::from(s),
+            Raw::List(l) => SupportedFunctions::Functions(l),
+            Raw::Filter(f) => SupportedFunctions::with_exclusions(
+                f.include.unwrap_or(SupportedFunctions::ALL),
+                f.exclude.map(NameList::get_names).unwrap_or_default(),
+            ),
+        })
+    }
+}
+
+///Why a configuration value was replaced by its default
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultReason {
+    Missing,
+    Invalid(String),
+}
+
+///Configuration value that fell back to its default
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigDefault {
+    pub path: String,
+    pub value: String,
+    pub reason: DefaultReason,
+}
+
+impl fmt::Display for ConfigDefault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            DefaultReason::Missing => write!(f, "{} is missing. Using default {}", self.path, self.value),
+            DefaultReason::Invalid(v) => write!(f, "{} has invalid value {}. Using default {}", self.path, v, self.value),
+        }
+    }
+}
+
+///What happened while loading the AI configuration: unknown keys, values that fell back to
+///their defaults, entries that could not be read (wrong types) and were skipped and
+///entries that replaced an earlier one with the same name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AIConfigReport {
+    pub unknown_keys: Vec<String>,
+    pub defaults: Vec<ConfigDefault>,
+    pub errors: Vec<String>,
+    pub duplicates: Vec<String>,
+    secrets: SecretValues,
+}
+
+impl AIConfigReport {
+    ///True if there are no unknown keys, errors or duplicates. Defaults are expected for optional keys.
+    pub fn is_clean(&self) -> bool {
+        self.unknown_keys.is_empty() && self.errors.is_empty() && self.duplicates.is_empty()
+    }
+
+    ///Secret values to redact from the messages of the report
+    pub(crate) fn set_secrets(&mut self, secrets: SecretValues) {
+        self.secrets = secrets;
+    }
+
+    pub(crate) fn add_error(&mut self, msg: String) {
+        log_error!("add_error", "Invalid entry in AI YML config file. Entry ignored. {}", &msg);
+        self.errors.push(msg);
+    }
+
+    pub(crate) fn add_duplicate(&mut self, path: &str, kind: &str, name: &str) {
+        let msg = format!("{}: duplicate {} {}. It replaces the earlier definition", path, kind, name);
+        log_warning!("add_duplicate", "{}", &msg);
+        self.duplicates.push(msg);
+    }
+
+    ///Defaults used for a configuration path (e.g. `dev.platform[1].models[0].system`)
+    pub fn get_default(&self, path: &str) -> Option<&ConfigDefault> {
+        self.defaults.iter().find(|d| d.path == path)
+    }
+
+    pub(crate) fn add_unknown_keys(&mut self, path: &str, extra: &BTreeMap<String, Value>) {
+        for k in extra.keys() {
+            let key_path = format!("{}.{}", path, k);
+            log_warning!("add_unknown_keys", "Unknown key {} in AI YML config file. Ignored", key_path);
+            self.unknown_keys.push(key_path);
+        }
+    }
+
+    ///The value if present, otherwise the default (recorded as missing)
+    pub(crate) fn get_or_default<T: fmt::Display>(&mut self, value: Option<T>, path: &str, default: T) -> T {
+        match value {
+            Some(v) => v,
+            None => {
+                self.add_default(path, &default, DefaultReason::Missing);
+                default
+            }
+        }
+    }
+
+    pub(crate) fn add_default<T: fmt::Display>(&mut self, path: &str, default: &T, reason: DefaultReason) {
+        let reason = match reason {
+            DefaultReason::Invalid(v) => DefaultReason::Invalid(self.secrets.redact(&v)),
+            r => r,
+        };
+        let d = ConfigDefault { path: path.to_owned(), value: default.to_string(), reason };
+        match d.reason {
+            DefaultReason::Missing => log_trace!("add_default", "{}", &d),
+            DefaultReason::Invalid(_) => log_warning!("add_default", "{}", &d),
+        }
+        self.defaults.push(d);
+    }
+
+    ///Deserialize a configuration entry. Errors are recorded and the entry skipped.
+    pub(crate) fn parse<T: DeserializeOwned>(&mut self, value: Value, path: &str) -> Option<T> {
+        match serde_path_to_error::deserialize(value) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                let msg = if e.path().to_string() == "." {
+                    format!("{}: {}", path, e.inner())
+                } else {
+                    format!("{}.{}: {}", path, e.path(), e.inner())
+                };
+                self.add_error(self.secrets.redact(&msg));
+                None
+            }
+        }
+    }
+}
+
+///JSON value of a YAML node so it can be deserialized with serde
+pub(crate) fn yaml_to_json(yaml: &Yaml) -> Value {
+    match yaml {
+        Yaml::Real(r) => r.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number).unwrap_or_else(|| Value::String(r.clone())),
+        Yaml::Integer(i) => Value::from(*i),
+        Yaml::String(s) => Value::String(s.clone()),
+        Yaml::Boolean(b) => Value::Bool(*b),
+        Yaml::Array(a) => Value::Array(a.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(h) => {
+            let mut m = Map::new();
+            for (k, v) in h {
+                let key = match k {
+                    Yaml::String(s) => s.clone(),
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Real(r) => r.clone(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    other => format!("{:?}", other),
+                };
+                m.insert(key, yaml_to_json(v));
+            }
+            Value::Object(m)
+        }
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_config_def {
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use serde_json::Value;
+    use yaml_rust2::YamlLoader;
+
+    use crate::ai_config::SupportedFunctions;
+
+    use super::{yaml_to_json, AIConfigReport, ModelDef};
+
+    #[test]
+    fn test_parse_model_def() {
+        build_logger("BACHUETECH", "BT.AI_CONFIG_DEF", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let yml = YamlLoader::load_from_str("
+- model_id: llama3.1
+  sytem: You are an AI assistant
+  tools: {exclude: shell_exec}
+- model_id: [wrong]
+").unwrap();
+        let mut report = AIConfigReport::default();
+        let json = yaml_to_json(&yml[0]);
+        let m: ModelDef = report.parse(json[0].clone(), "models[0]").unwrap();
+        assert_eq!(m.tools.unwrap(), SupportedFunctions::Except(Box::new(SupportedFunctions::ALL), vec!["shell_exec".to_owned()]));
+        report.add_unknown_keys("models[0]", &m.extra);
+        assert_eq!(report.unknown_keys, vec!["models[0].sytem"]);
+
+        assert!(report.parse::<ModelDef>(json[1].clone(), "models[1]").is_none());
+        assert!(report.errors[0].starts_with("models[1].model_id: invalid type"));
+        assert_eq!(report.get_or_default(None, "models[1].system", "Default".to_owned()), "Default");
+        assert!(report.get_default("models[1].system").is_some());
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_yaml_to_json() {
+        let yml = YamlLoader::load_from_str("a: 1\nb: 2.5\nc: [true, ~, x]\n3: y").unwrap();
+        let json = yaml_to_json(&yml[0]);
+        assert_eq!(json["a"], 1);
+        assert_eq!(json["b"], 2.5);
+        assert_eq!(json["c"], serde_json::json!([true, null, "x"]));
+        assert_eq!(json["3"], Value::from("y"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::mem;
+
+use yaml_rust2::Yaml;
+
+///Section every environment inherits from, if present
+pub const COMMON_SECTION: &str = "common";
+///Key of an environment with the environment (or list of environments) it inherits from
+pub const EXTENDS_KEY: &str = "extends";
+
+///Key that identifies the entries of the lists merged entry by entry. Other lists are replaced.
+fn get_identity_key(list_key: &str) -> Option<&'static str> {
+    match list_key {
+        "platform" => Some("name"),
+        "models" => Some("model_id"),
+        _ => None,
+    }
+}
+
+///Configuration of an environment after applying `common` and `extends`
+pub struct EffectiveEnv {
+    ///Merged environment section. `BadValue` if the environment is not defined.
+    pub yaml: Yaml,
+    ///Environments merged, from the base to the requested one
+    pub chain: Vec<String>,
+    ///Unknown or circular `extends` references. They are ignored.
+    pub problems: Vec<String>,
+}
+
+///Merge the environment `run_env` of an AI config YAML document over the environments it inherits from:
+///first `common` (if defined), then each environment in `extends` (a name or a list, resolved recursively), then `run_env` itself.
+///
+///Maps are merged key by key. `platform` entries are merged by `name` and `models` entries by `model_id`;
+///entries with a new name are appended. Any other value, lists included, replaces the inherited one.
+pub fn get_effective_env(ai_config: &Yaml, run_env: &str) -> EffectiveEnv {
+    let mut effective = EffectiveEnv { yaml: Yaml::BadValue, chain: Vec::new(), problems: Vec::new() };
+    if ai_config[run_env].as_hash().is_none() {
+        return effective;
+    }
+
+    add_to_chain(ai_config, run_env, &mut Vec::new(), &mut effective);
+    if run_env != COMMON_SECTION && ai_config[COMMON_SECTION].as_hash().is_some() && !effective.chain.iter().any(|e| e == COMMON_SECTION) {
+        effective.chain.insert(0, COMMON_SECTION.to_owned());
+    }
+
+    let mut merged = Yaml::Hash(Default::default());
+    for env in &effective.chain {
+        let mut section = ai_config[env.as_str()].clone();
+        if let Yaml::Hash(h) = &mut section {
+            h.remove(&Yaml::String(EXTENDS_KEY.to_owned()));
+        }
+        merged = merge_yaml(merged, &section, "");
+    }
+    effective.yaml = merged;
+    effective
+}
+
+fn add_to_chain(ai_config: &Yaml, env: &str, visiting: &mut Vec<String>, effective: &mut EffectiveEnv) {
+    visiting.push(env.to_owned());
+    let bases: Vec<String> = match &ai_config[env][EXTENDS_KEY] {
+        Yaml::String(s) => vec![s.trim().to_owned()],
+        Yaml::Array(a) => a.iter().filter_map(|b| b.as_str().map(|s| s.trim().to_owned())).collect(),
+        Yaml::BadValue | Yaml::Null => Vec::new(),
+        other => {
+            effective.problems.push(format!("{}.{}: Invalid value {:?}. Expected an environment name or a list of names", env, EXTENDS_KEY, other));
+            Vec::new()
+        },
+    };
+    for base in bases {
+        if visiting.contains(&base) {
+            effective.problems.push(format!("{}.{}: Circular reference to {}. Ignored", env, EXTENDS_KEY, base));
+        } else if ai_config[base.as_str()].as_hash().is_none() {
+            effective.problems.push(format!("{}.{}: Environment {} is not defined. Ignored", env, EXTENDS_KEY, base));
+        } else {
+            add_to_chain(ai_config, &base, visiting, effective);
+        }
+    }
+    visiting.pop();
+    if !effective.chain.iter().any(|e| e == env) {
+        effective.chain.push(env.to_owned());
+    }
+}
+
+fn merge_yaml(base: Yaml, over: &Yaml, key: &str) -> Yaml {
+    match (base, over) {
+        (Yaml::Hash(mut b), Yaml::Hash(o)) => {
+            for (k, v) in o {
+                match b.get_mut(k) {
+                    Some(bv) => {
+                        let inherited = mem::replace(bv, Yaml::Null);
+                        *bv = merge_yaml(inherited, v, k.as_str().unwrap_or_default());
+                    },
+                    None => { b.insert(k.clone(), v.clone()); },
+                }
+            }
+            Yaml::Hash(b)
+        },
+        (Yaml::Array(mut b), Yaml::Array(o)) if get_identity_key(key).is_some() => {
+            let id_key = get_identity_key(key).unwrap_or_default();
+            for item in o {
+                let existing = item[id_key].as_str().and_then(|id| b.iter().position(|bi| bi[id_key].as_str() == Some(id)));
+                match existing {
+                    Some(pos) => {
+                        let inherited = mem::replace(&mut b[pos], Yaml::Null);
+                        b[pos] = merge_yaml(inherited, item, "");
+                    },
+                    None => b.push(item.clone()),
+                }
+            }
+            Yaml::Array(b)
+        },
+        (_, o) => o.clone(),
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_config_merge {
+    use yaml_rust2::YamlLoader;
+
+    use super::get_effective_env;
+
+    const CONFIG: &str = "
+name: BT_AI
+common:
+  tool_groups:
+    math: [do_basic_math]
+  platform:
+    - name: LOCAL
+      server: {host: localhost, port: 11434, secure: false}
+      api: {ctx_max: 10, wire_format: ollama}
+      models:
+        - model_id: default
+          model: llama3.3:70b
+          system: You are an AI assistant
+          tools: NONE
+        - model_id: llama3.1
+          model: llama3.1:8b
+          tools: [do_basic_math, do_math_expressions]
+qa:
+  platform:
+    - name: LOCAL
+      server: {host: qa-ai.internal}
+      models:
+        - model_id: llama3.1
+          tools: ['@math']
+prod:
+  extends: qa
+  platform:
+    - name: REMOTE
+      server: {host: ai.example.com, port: 443}
+    - name: LOCAL
+      api: {ctx_max: 20}
+loop_a:
+  extends: [loop_b, missing]
+loop_b:
+  extends: loop_a
+";
+
+    #[test]
+    fn test_effective_env_merge() {
+        let yml = YamlLoader::load_from_str(CONFIG).unwrap();
+        let env = get_effective_env(&yml[0], "prod");
+        assert!(env.problems.is_empty());
+        assert_eq!(env.chain, vec!["common", "qa", "prod"]);
+        assert!(env.yaml["extends"].is_badvalue());
+
+        let local = &env.yaml["platform"][0];
+        assert_eq!(local["server"]["host"].as_str().unwrap(), "qa-ai.internal");
+        assert_eq!(local["server"]["port"].as_i64().unwrap(), 11434);
+        assert_eq!(local["api"]["ctx_max"].as_i64().unwrap(), 20);
+        assert_eq!(local["api"]["wire_format"].as_str().unwrap(), "ollama");
+        assert_eq!(local["models"].as_vec().unwrap().len(), 2);
+        //Lists other than platform and models are replaced
+        assert_eq!(local["models"][1]["tools"].as_vec().unwrap().len(), 1);
+        assert_eq!(local["models"][1]["model"].as_str().unwrap(), "llama3.1:8b");
+        assert_eq!(env.yaml["platform"][1]["name"].as_str().unwrap(), "REMOTE");
+        assert_eq!(env.yaml["tool_groups"]["math"][0].as_str().unwrap(), "do_basic_math");
+    }
+
+    #[test]
+    fn test_effective_env_problems() {
+        let yml = YamlLoader::load_from_str(CONFIG).unwrap();
+        let env = get_effective_env(&yml[0], "loop_a");
+        assert_eq!(env.chain, vec!["common", "loop_b", "loop_a"]);
+        assert_eq!(env.problems, vec![
+            "loop_b.extends: Circular reference to loop_a. Ignored",
+            "loop_a.extends: Environment missing is not defined. Ignored",
+        ]);
+
+        let env = get_effective_env(&yml[0], "UNKNOWN");
+        assert!(env.yaml.is_badvalue());
+        assert!(env.chain.is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    ai_config::{AIConfig, SupportedFunctions},
+    ai_config_def::DefaultReason,
+    ai_tools::{AIToolManager, ToolRegistrationError, ToolSelectionError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigIssueLevel {
+    ///The configuration does not work as written
+    Error,
+    ///The configuration works but probably not as intended
+    Warning,
+}
+
+///Problem found in the AI configuration, with the path of the offending entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub level: ConfigIssueLevel,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            ConfigIssueLevel::Error => "ERROR",
+            ConfigIssueLevel::Warning => "WARNING",
+        };
+        write!(f, "{} {}: {}", level, self.path, self.message)
+    }
+}
+
+///All the problems found in the AI configuration of an environment and its tools definition
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigValidation {
+    issues: Vec<ConfigIssue>,
+}
+
+impl ConfigValidation {
+    fn add(&mut self, level: ConfigIssueLevel, path: &str, message: String) {
+        self.issues.push(ConfigIssue { level, path: path.to_owned(), message });
+    }
+
+    ///True if there are no errors. Warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.get_errors().is_empty()
+    }
+
+    pub fn get_issues(&self) -> &Vec<ConfigIssue> {
+        &self.issues
+    }
+
+    pub fn get_errors(&self) -> Vec<&ConfigIssue> {
+        self.issues.iter().filter(|i| i.level == ConfigIssueLevel::Error).collect()
+    }
+
+    pub fn get_warnings(&self) -> Vec<&ConfigIssue> {
+        self.issues.iter().filter(|i| i.level == ConfigIssueLevel::Warning).collect()
+    }
+}
+
+impl AIConfig {
+    ///Load the AI configuration of `run_env` and the JSON tools definition and report every problem found
+    ///A tools definition that cannot be read or parsed is an error, even if no model uses tools
+    pub fn validate(run_env: &str) -> Result<ConfigValidation, Box<dyn Error>> {
+        Ok(validate_config(&AIToolManager::new(run_env)?, run_env))
+    }
+}
+
+///Problems of the AI configuration of a tool manager (loaded for `run_env`) and of its tool definitions
+pub fn validate_config(tool_manager: &AIToolManager, run_env: &str) -> ConfigValidation {
+    let ai_config = tool_manager.get_ai_config();
+    let report = ai_config.get_load_report();
+    let mut validation = ConfigValidation::default();
+
+    for e in &report.errors {
+        let (path, message) = e.split_once(": ").unwrap_or((run_env, e));
+        validation.add(ConfigIssueLevel::Error, path, format!("Invalid entry ignored. {}", message));
+    }
+    for d in &report.duplicates {
+        let (path, message) = d.split_once(": ").unwrap_or((run_env, d));
+        validation.add(ConfigIssueLevel::Error, path, message.to_owned());
+    }
+    for d in &report.defaults {
+        if let DefaultReason::Invalid(v) = &d.reason {
+            validation.add(ConfigIssueLevel::Error, &d.path, format!("Invalid value {}. Default {} is used instead", v, d.value));
+        }
+    }
+    for k in &report.unknown_keys {
+        validation.add(ConfigIssueLevel::Warning, k, "Unknown key. It is ignored".to_owned());
+    }
+
+    let mut platforms = ai_config.get_platform_list();
+    platforms.sort();
+    if platforms.is_empty() {
+        validation.add(ConfigIssueLevel::Error, run_env, "No platforms defined for this environment".to_owned());
+    }
+    for p in &platforms {
+        let Some(models) = ai_config.get_models(p) else {
+            continue;
+        };
+        let plat_path = ai_config.get_platform_path(p).cloned().unwrap_or_else(|| run_env.to_owned());
+        if !models.contains_key("default") {
+            validation.add(ConfigIssueLevel::Warning, &plat_path, "No default model. Unknown model ids get no system message and no tools".to_owned());
+        }
+
+        let mut model_ids: Vec<&String> = models.keys().collect();
+        model_ids.sort();
+        for m in model_ids {
+            let functions = models[m].tools.clone();
+            if functions == SupportedFunctions::NONE {
+                continue;
+            }
+            let tools_path = format!("{}.tools", ai_config.get_model_path(p, m).unwrap_or(&plat_path));
+            match tool_manager.try_get_common_tools(functions) {
+                Ok(_) => {},
+                Err(ToolSelectionError::UndefinedTools(names)) => {
+                    validation.add(ConfigIssueLevel::Error, &tools_path, format!("Tools not defined in the tools definition: {}", names.join(", ")));
+                },
+                Err(e) => validation.add(ConfigIssueLevel::Error, &tools_path, e.to_string()),
+            }
+        }
+    }
+
+    match tool_manager.get_load_error() {
+        Some(e @ (ToolRegistrationError::Io{ path, .. } | ToolRegistrationError::Parse{ path, .. })) => validation.add(ConfigIssueLevel::Error, path, e.to_string()),
+        Some(e) => validation.add(ConfigIssueLevel::Error, "tools", e.to_string()),
+        None => {},
+    }
+    for p in tool_manager.get_definition_problems() {
+        validation.add(ConfigIssueLevel::Warning, &p.path, format!("Tool '{}': {}", p.tool, p.message));
+    }
+
+    validation
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_config_validation {
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use yaml_rust2::YamlLoader;
+
+    use crate::{ai_config::AIConfig, ai_tools::AIToolManager};
+
+    use super::{validate_config, ConfigIssueLevel};
+
+    #[test]
+    fn test_validate_dev() {
+        build_logger("BACHUETECH", "BT.AI_CONFIG_VALIDATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let v = AIConfig::validate("dev").unwrap();
+        assert!(!v.is_valid());
+        let errors = v.get_errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, "dev.platform[0].server.port");
+        assert_eq!(errors[1].path, "dev.platform[0].api.ctx_max");
+        //Platform wrong has no models
+        assert_eq!(v.get_warnings()[0].path, "dev.platform[0]");
+    }
+
+    #[test]
+    fn test_validate_all_problems() {
+        build_logger("BACHUETECH", "BT.AI_CONFIG_VALIDATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let yml = YamlLoader::load_from_str("
+prod:
+  platform:
+    - name: LOCAL
+      server: {host: localhost, port: 11434, secure: false}
+      api: {ctx_max: 10}
+      models:
+        - model_id: llama3.1
+          tools: [do_basic_math, shell_exec]
+        - model_id: llama3.1
+          sytem: You are a pirate
+          tools: NONE
+    - name: LOCAL
+      server: {host: localhost, port: 11435, secure: false}
+      models:
+        - model_id: default
+          tools: ALL
+").unwrap();
+        let tm = AIToolManager::new_with_config(AIConfig::new_from_yaml(&yml[0], "prod"));
+        let v = validate_config(&tm, "prod");
+        let issues: Vec<String> = v.get_issues().iter().map(|i| i.to_string()).collect();
+
+        assert_eq!(v.get_errors().len(), 2);
+        assert!(issues.contains(&"ERROR prod.platform[1]: duplicate platform name LOCAL. It replaces the earlier definition".to_owned()));
+        assert!(issues.contains(&"ERROR prod.platform[0].models[1]: duplicate model_id llama3.1. It replaces the earlier definition".to_owned()));
+        assert!(issues.contains(&"WARNING prod.platform[0].models[1].sytem: Unknown key. It is ignored".to_owned()));
+        assert!(v.get_issues().iter().all(|i| i.level == ConfigIssueLevel::Error || i.path.ends_with("sytem")));
+    }
+
+    #[test]
+    fn test_validate_undefined_tools() {
+        build_logger("BACHUETECH", "BT.AI_CONFIG_VALIDATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let yml = YamlLoader::load_from_str("
+prod:
+  platform:
+    - name: LOCAL
+      server: {host: localhost, port: 11434, secure: false}
+      api: {ctx_max: 10}
+      models:
+        - model_id: llama3.1
+          tools: [do_basic_math, shell_exec, '@web']
+").unwrap();
+        let tm = AIToolManager::new_with_config(AIConfig::new_from_yaml(&yml[0], "prod"));
+        let v = validate_config(&tm, "prod");
+        assert_eq!(v.get_errors()[0].path, "prod.platform[0].models[0].tools");
+        assert_eq!(v.get_errors()[0].message, "Tools not defined in the tools definition: shell_exec, @web");
+        assert_eq!(v.get_warnings()[0].path, "prod.platform[0]");
+    }
+}
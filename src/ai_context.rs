@@ -0,0 +1,111 @@
+use bt_logger::log_verbose;
+
+use crate::message::{Message, MessageRole};
+
+///Conversation history after applying the context limit of a platform
+#[derive(Debug, Clone)]
+pub struct TrimmedContext {
+    pub messages: Vec<Message>,
+    ///Number of messages removed from the history, oldest first
+    pub dropped: usize,
+}
+
+///Keep the most recent messages of a conversation history, up to `ctx_max` messages (the `ctx_max` of the platform).
+///
+///SYSTEM messages are always kept, first, and do not count. An assistant message with tool calls and the TOOL messages that
+///answer it are kept or dropped together, so a group that does not fit is dropped whole and older messages with it.
+///TOOL messages left at the start of the kept history, without their tool call, are dropped as well.
+pub fn trim_context(context: Vec<Message>, ctx_max: usize) -> TrimmedContext {
+    let total = context.len();
+    let (mut messages, groups) = split_context(context);
+    messages.extend(keep_newest(groups, ctx_max, |g| g.len()));
+    let dropped = total - messages.len();
+    if dropped > 0 {
+        log_verbose!("trim_context", "{} of {} context messages dropped to fit the context limit of {} messages", dropped, total, ctx_max);
+    }
+    TrimmedContext { messages, dropped }
+}
+
+///SYSTEM messages and the other messages in groups that must stay together (a tool call and its results), oldest first
+pub(crate) fn split_context(context: Vec<Message>) -> (Vec<Message>, Vec<Vec<Message>>) {
+    let mut system: Vec<Message> = Vec::new();
+    let mut groups: Vec<Vec<Message>> = Vec::new();
+    for m in context {
+        if m.get_role() == MessageRole::SYSTEM {
+            system.push(m);
+        } else if m.get_role() == MessageRole::TOOL && let Some(last) = groups.last_mut() && last[0].has_tool_calls() {
+            last.push(m);
+        } else {
+            groups.push(vec![m]);
+        }
+    }
+    (system, groups)
+}
+
+///Messages of the newest groups whose total `cost` is at most `limit`. Stops at the first group that does not fit.
+pub(crate) fn keep_newest(groups: Vec<Vec<Message>>, limit: usize, cost: impl Fn(&[Message]) -> usize) -> Vec<Message> {
+    let mut used = 0;
+    let first_kept = groups.iter().rposition(|g| {
+        used += cost(g);
+        used > limit
+    }).map_or(0, |i| i + 1);
+    groups.into_iter().skip(first_kept).skip_while(|g| g[0].get_role() == MessageRole::TOOL).flatten().collect()
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_context {
+    use std::collections::HashMap;
+
+    use crate::{ai_tool_to_call::ToolToCall, message::{Message, MessageRole}};
+
+    use super::trim_context;
+
+    fn get_contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|m| m.get_content().as_str()).collect()
+    }
+
+    fn get_history() -> Vec<Message> {
+        let call = ToolToCall::new("do_basic_math".to_owned(), HashMap::new());
+        vec![
+            Message::new(MessageRole::SYSTEM, "sys".to_owned()),
+            Message::new(MessageRole::USER, "u1".to_owned()),
+            Message::new(MessageRole::ASSISTANT, "a1".to_owned()),
+            Message::new(MessageRole::USER, "u2".to_owned()),
+            Message::new_with_tools(MessageRole::ASSISTANT, "call".to_owned(), vec![call.clone(), call]),
+            Message::new_tool_result("r1".to_owned(), None),
+            Message::new_tool_result("r2".to_owned(), None),
+            Message::new(MessageRole::ASSISTANT, "a2".to_owned()),
+        ]
+    }
+
+    #[test]
+    fn test_trim_context() {
+        let t = trim_context(get_history(), 10);
+        assert_eq!(t.dropped, 0);
+        assert_eq!(t.messages.len(), 8);
+
+        let t = trim_context(get_history(), 5);
+        assert_eq!(get_contents(&t.messages), vec!["sys", "u2", "call", "r1", "r2", "a2"]);
+        assert_eq!(t.dropped, 2);
+
+        //The tool call group does not fit: dropped whole
+        let t = trim_context(get_history(), 3);
+        assert_eq!(get_contents(&t.messages), vec!["sys", "a2"]);
+        assert_eq!(t.dropped, 6);
+
+        let t = trim_context(get_history(), 0);
+        assert_eq!(get_contents(&t.messages), vec!["sys"]);
+    }
+
+    #[test]
+    fn test_trim_orphan_tool_results() {
+        let mut history = get_history().split_off(5);
+        history.push(Message::new(MessageRole::USER, "u3".to_owned()));
+        let t = trim_context(history, 10);
+        assert_eq!(get_contents(&t.messages), vec!["a2", "u3"]);
+        assert_eq!(t.dropped, 2);
+    }
+}
@@ -0,0 +1,211 @@
+use bt_logger::log_error;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    ai_config::WireFormat,
+    ai_openai_helper::{get_ai_embed_response_from_openai, get_openai_embed_request_json},
+};
+
+//Request and response types of the end points other than chat and generate, in the Ollama (internal) shape.
+//Only embeddings are converted to and from the OpenAI shape; show, pull, ps and version are Ollama end points.
+
+///Text or list of texts to embed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EmbedInput {
+    Text(String),
+    Texts(Vec<String>),
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AIEmbedRequest {
+    pub model: String,
+    pub input: EmbedInput,
+    ///Truncate the input to the context length of the model instead of failing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+}
+
+///One embedding vector per input text, in the order of the input
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AIEmbedResponse {
+    #[serde(default)]
+    pub model: String,
+    pub embeddings: Vec<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_duration: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_duration: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_eval_count: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AIShowRequest {
+    pub model: String,
+    ///Include the full tokenizer data in `model_info`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AIModelDetails {
+    #[serde(default)]
+    pub parent_model: String,
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub family: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub families: Option<Vec<String>>,
+    #[serde(default)]
+    pub parameter_size: String,
+    #[serde(default)]
+    pub quantization_level: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AIShowResponse {
+    #[serde(default)]
+    pub modelfile: String,
+    #[serde(default)]
+    pub parameters: String,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub details: AIModelDetails,
+    ///Architecture specific values (e.g. `llama.context_length`)
+    #[serde(default)]
+    pub model_info: Map<String, Value>,
+    ///e.g. `completion`, `tools`, `embedding`, `vision`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AIPullRequest {
+    pub model: String,
+    ///Allow registries without TLS. Development only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure: Option<bool>,
+    pub stream: bool,
+}
+
+///Progress of a pull. A non streamed pull returns only the final status (`success`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AIPullStatus {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+///Models loaded in memory (`ps`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AIRunningModels {
+    #[serde(default)]
+    pub models: Vec<AIRunningModel>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AIRunningModel {
+    pub name: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub digest: String,
+    #[serde(default)]
+    pub details: AIModelDetails,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub size_vram: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AIVersionResponse {
+    pub version: String,
+}
+
+pub fn get_embed_request(ai_model: &str, input: EmbedInput) -> AIEmbedRequest {
+    AIEmbedRequest { model: ai_model.to_owned(), input, truncate: None, keep_alive: None }
+}
+
+///JSON body of a request. Used for the Ollama shapes; only the model name is kept if it cannot be serialized.
+pub fn get_request_json<T: Serialize>(request: &T, model: &str) -> String {
+    match serde_json::to_string(request) {
+        Ok(sj) => sj,
+        Err(e) => {
+            let bem = format!("{{\"model\": \"{}\"}}", model);
+            log_error!("get_request_json", "Error creating JSON Request. Returning default message as a best effort: {}. Error: {}", &bem, e);
+            bem
+        }
+    }
+}
+
+///JSON body of the embeddings request in the wire format of the target platform
+pub fn get_embed_request_json_by_format(request: &AIEmbedRequest, wire_format: &WireFormat) -> String {
+    match wire_format {
+        WireFormat::OLLAMA => get_request_json(request, &request.model),
+        WireFormat::OPENAI => get_openai_embed_request_json(request),
+    }
+}
+
+///Decode an embeddings response body in the wire format of the source platform
+pub fn get_embed_response_by_format(body: &str, wire_format: &WireFormat) -> Result<AIEmbedResponse, serde_json::Error> {
+    match wire_format {
+        WireFormat::OLLAMA => serde_json::from_str(body),
+        WireFormat::OPENAI => get_ai_embed_response_from_openai(body),
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_endpoint_helper {
+    use crate::ai_config::WireFormat;
+
+    use super::{get_embed_request, get_embed_request_json_by_format, get_embed_response_by_format, AIRunningModels, AIShowResponse, EmbedInput};
+
+    #[test]
+    fn test_embed_by_format() {
+        let mut req = get_embed_request("nomic-embed-text", EmbedInput::Texts(vec!["a".to_owned(), "b".to_owned()]));
+        req.truncate = Some(true);
+        assert_eq!(get_embed_request_json_by_format(&req, &WireFormat::OLLAMA), "{\"model\":\"nomic-embed-text\",\"input\":[\"a\",\"b\"],\"truncate\":true}");
+        //OpenAI rejects unknown fields
+        let req = get_embed_request("text-embedding-3-small", EmbedInput::Text("a".to_owned()));
+        assert_eq!(get_embed_request_json_by_format(&req, &WireFormat::OPENAI), "{\"model\":\"text-embedding-3-small\",\"input\":\"a\"}");
+
+        let resp = get_embed_response_by_format("{\"model\":\"nomic-embed-text\",\"embeddings\":[[0.1,0.2],[0.3,0.4]],\"prompt_eval_count\":2}", &WireFormat::OLLAMA).unwrap();
+        assert_eq!(resp.embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+        //OpenAI data may come in any order
+        let resp = get_embed_response_by_format("{\"object\":\"list\",\"model\":\"text-embedding-3-small\",\"data\":[{\"object\":\"embedding\",\"index\":1,\"embedding\":[0.3]},{\"object\":\"embedding\",\"index\":0,\"embedding\":[0.1]}],\"usage\":{\"prompt_tokens\":2,\"total_tokens\":2}}", &WireFormat::OPENAI).unwrap();
+        assert_eq!(resp.embeddings, vec![vec![0.1], vec![0.3]]);
+        assert_eq!(resp.prompt_eval_count, Some(2));
+    }
+
+    #[test]
+    fn test_show_and_ps_responses() {
+        let show: AIShowResponse = serde_json::from_str("{\"modelfile\":\"FROM llama3.1\",\"parameters\":\"num_ctx 8192\",\"template\":\"{{ .Prompt }}\",\"details\":{\"format\":\"gguf\",\"family\":\"llama\",\"families\":[\"llama\"],\"parameter_size\":\"8.0B\",\"quantization_level\":\"Q4_K_M\"},\"model_info\":{\"llama.context_length\":131072},\"capabilities\":[\"completion\",\"tools\"]}").unwrap();
+        assert_eq!(show.details.parameter_size, "8.0B");
+        assert_eq!(show.model_info["llama.context_length"], 131072);
+        assert!(show.capabilities.contains(&"tools".to_owned()));
+
+        let ps: AIRunningModels = serde_json::from_str("{\"models\":[{\"name\":\"llama3.1:8b\",\"model\":\"llama3.1:8b\",\"size\":6654289920,\"digest\":\"46e0c10c\",\"expires_at\":\"2025-03-27T18:50:00Z\",\"size_vram\":6654289920}]}").unwrap();
+        assert_eq!(ps.models[0].name, "llama3.1:8b");
+        assert_eq!(ps.models[0].details.family, "");
+    }
+}
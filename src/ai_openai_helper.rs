@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use bt_logger::{log_error, log_warning};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    ai_chat_helper::{AIChatRequest, AIChatResponse},
+    ai_endpoint_helper::{AIEmbedRequest, AIEmbedResponse, EmbedInput},
+    ai_tool_to_call::ToolToCall,
+    ai_tools::Tool,
+    message::{Message, MessageRole},
+};
+
+const SSE_DATA_PREFIX: &str = "data:";
+const SSE_DONE: &str = "[DONE]";
+
+///Chat request in the OpenAI `/v1/chat/completions` shape
+#[derive(Serialize, Debug)]
+pub struct OpenAIChatRequest {
+    pub model: String,
+    pub messages: Vec<OpenAIMessage>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<OpenAIStreamOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIStreamOptions {
+    pub include_usage: bool,
+}
+
+///Message in the OpenAI shape. Also used for the `delta` of streamed chunks, where every field is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpenAIMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIToolCall {
+    ///Only present in streamed chunks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>, // "function"
+    pub function: OpenAIFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIFunctionCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    ///JSON object encoded as a string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIChatResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<OpenAIChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIChoice {
+    #[serde(default)]
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<OpenAIMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<OpenAIMessage>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+}
+
+///Embeddings request in the OpenAI `/v1/embeddings` shape
+#[derive(Serialize, Debug)]
+pub struct OpenAIEmbedRequest {
+    pub model: String,
+    pub input: EmbedInput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIEmbedResponse {
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub data: Vec<OpenAIEmbedding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenAIEmbedding {
+    #[serde(default)]
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+fn get_role(role: &Option<String>) -> MessageRole {
+    match role.as_deref() {
+        Some("system") => MessageRole::SYSTEM,
+        Some("user") => MessageRole::USER,
+        Some("tool") => MessageRole::TOOL,
+        _ => MessageRole::ASSISTANT,
+    }
+}
+
+fn get_arguments(function_name: &str, arguments: Option<&String>) -> HashMap<String, Value> {
+    match arguments.map(|a| a.trim()) {
+        None | Some("") => HashMap::new(),
+        Some(args) => match serde_json::from_str(args) {
+            Ok(a) => a,
+            Err(e) => {
+                log_warning!("get_arguments", "Arguments '{}' of tool call {} are not a JSON object. Using no arguments. Error: {}", args, function_name, e);
+                HashMap::new()
+            }
+        },
+    }
+}
+
+fn get_tool_to_call(call_id: Option<String>, function_name: String, arguments: Option<&String>) -> ToolToCall {
+    let args = get_arguments(&function_name, arguments);
+    match call_id {
+        Some(id) => ToolToCall::new_with_id(id, function_name, args),
+        None => ToolToCall::new(function_name, args),
+    }
+}
+
+impl From<&Message> for OpenAIMessage {
+    fn from(msg: &Message) -> Self {
+        let role = match msg.get_role() {
+            MessageRole::IPYTHON => MessageRole::TOOL.as_str(),
+            r => r.as_str(),
+        };
+        let tool_calls = msg.get_tools().map(|tools| {
+            tools.iter().map(|t| OpenAIToolCall {
+                index: None,
+                id: t.get_id().cloned(),
+                type_: Some("function".to_owned()),
+                function: OpenAIFunctionCall {
+                    name: Some(t.get_function_name().clone()),
+                    arguments: Some(serde_json::to_string(t.get_raw_arguments()).unwrap_or("{}".to_owned())),
+                },
+            }).collect()
+        });
+
+        OpenAIMessage {
+            role: Some(role.to_owned()),
+            content: Some(msg.get_content().clone()),
+            tool_calls,
+            tool_call_id: msg.get_tool_call_id().cloned(),
+        }
+    }
+}
+
+impl From<&AIChatRequest> for OpenAIChatRequest {
+    fn from(ai_request: &AIChatRequest) -> Self {
+        let mut messages: Vec<OpenAIMessage> = ai_request.messages.iter().map(OpenAIMessage::from).collect();
+
+        //OpenAI requires an id on every tool call and on the TOOL message that answers it. Ollama does not send them,
+        //so assign the missing call ids and give them, in order, to the following TOOL messages without id.
+        let mut call_num = 0;
+        let mut pending_ids: VecDeque<String> = VecDeque::new();
+        for m in messages.iter_mut() {
+            if let Some(calls) = m.tool_calls.as_mut() {
+                for c in calls.iter_mut().filter(|c| c.id.is_none()) {
+                    c.id = Some(format!("call_{}", call_num));
+                    call_num += 1;
+                }
+                pending_ids = calls.iter().filter_map(|c| c.id.clone()).collect();
+            } else if m.role.as_deref() == Some(MessageRole::TOOL.as_str()) {
+                match &m.tool_call_id {
+                    Some(id) => pending_ids.retain(|p| p != id),
+                    None => m.tool_call_id = pending_ids.pop_front(),
+                }
+            }
+        }
+
+        OpenAIChatRequest {
+            model: ai_request.model.clone(),
+            messages,
+            stream: ai_request.stream,
+            tools: ai_request.tools.clone(),
+            stream_options: if ai_request.stream { Some(OpenAIStreamOptions { include_usage: true }) } else { None },
+        }
+    }
+}
+
+impl From<OpenAIChatResponse> for AIChatResponse {
+    fn from(resp: OpenAIChatResponse) -> Self {
+        let choice = resp.choices.into_iter().next();
+        let finish_reason = choice.as_ref().and_then(|c| c.finish_reason.clone());
+        let oai_msg = choice.and_then(|c| c.message.or(c.delta)).unwrap_or_default();
+
+        let role = get_role(&oai_msg.role);
+        let content = oai_msg.content.unwrap_or_default();
+        let message = match oai_msg.tool_calls {
+            Some(calls) if !calls.is_empty() => {
+                let tools = calls.into_iter()
+                    .map(|c| get_tool_to_call(c.id, c.function.name.unwrap_or_default(), c.function.arguments.as_ref()))
+                    .collect();
+                Message::new_with_tools(role, content, tools)
+            }
+            _ => Message::new(role, content),
+        };
+
+        AIChatResponse {
+            model: resp.model,
+            created_at: resp.created.to_string(),
+            message,
+            done: finish_reason.is_some(),
+            done_reason: finish_reason,
+            total_duration: None,
+            load_duration: None,
+            prompt_eval_count: resp.usage.as_ref().map(|u| u.prompt_tokens),
+            prompt_eval_duration: None,
+            eval_count: resp.usage.as_ref().map(|u| u.completion_tokens),
+            eval_duration: None,
+        }
+    }
+}
+
+pub fn get_openai_chat_request_json(ai_request: &AIChatRequest) -> String {
+    let oai_request = OpenAIChatRequest::from(ai_request);
+    match serde_json::to_string(&oai_request) {
+        Ok(sj) => sj,
+        Err(e) => {
+            let bem = format!("{{\"model\": \"{}\", \"messages\": [], \"stream\": false}}", &ai_request.model);
+            log_error!("get_openai_chat_request_json", "Error creating OpenAI JSON Request. Returning default message as a best effort: {}. Error: {}", &bem, e);
+            bem
+        }
+    }
+}
+
+pub fn get_ai_chat_response_from_openai(body: &str) -> Result<AIChatResponse, serde_json::Error> {
+    let oai_resp: OpenAIChatResponse = serde_json::from_str(body)?;
+    Ok(AIChatResponse::from(oai_resp))
+}
+
+pub fn get_openai_embed_request_json(ai_request: &AIEmbedRequest) -> String {
+    let oai_request = OpenAIEmbedRequest { model: ai_request.model.clone(), input: ai_request.input.clone() };
+    match serde_json::to_string(&oai_request) {
+        Ok(sj) => sj,
+        Err(e) => {
+            let bem = format!("{{\"model\": \"{}\", \"input\": []}}", &ai_request.model);
+            log_error!("get_openai_embed_request_json", "Error creating OpenAI JSON Request. Returning default message as a best effort: {}. Error: {}", &bem, e);
+            bem
+        }
+    }
+}
+
+///Embeddings of an OpenAI response, in the order of the input
+pub fn get_ai_embed_response_from_openai(body: &str) -> Result<AIEmbedResponse, serde_json::Error> {
+    let mut oai_resp: OpenAIEmbedResponse = serde_json::from_str(body)?;
+    oai_resp.data.sort_by_key(|e| e.index);
+    Ok(AIEmbedResponse {
+        model: oai_resp.model,
+        embeddings: oai_resp.data.into_iter().map(|e| e.embedding).collect(),
+        total_duration: None,
+        load_duration: None,
+        prompt_eval_count: oai_resp.usage.map(|u| u.prompt_tokens),
+    })
+}
+
+#[derive(Debug, Default)]
+struct StreamedToolCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+}
+
+///Accumulates an OpenAI Server-Sent Events stream (`data: {chunk}` lines) into a single `AIChatResponse`
+#[derive(Debug, Default)]
+pub struct OpenAIStreamAccumulator {
+    pending: String,
+    model: String,
+    created: u64,
+    role: Option<String>,
+    content: String,
+    tool_calls: BTreeMap<usize, StreamedToolCall>,
+    finish_reason: Option<String>,
+    usage: Option<OpenAIUsage>,
+    done: bool,
+}
+
+impl OpenAIStreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Add a piece of the stream. Chunks may hold several SSE lines or split a line in two.
+    ///Returns the number of lines that could not be parsed.
+    pub fn push_chunk(&mut self, chunk: &str) -> usize {
+        self.pending.push_str(chunk);
+        let mut errors = 0;
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            if self.push_line(&line).is_err() {
+                errors += 1;
+            }
+        }
+        errors
+    }
+
+    ///Flush a trailing line that did not end with a new line
+    pub fn finish(&mut self) -> usize {
+        if self.pending.trim().is_empty() {
+            return 0;
+        }
+        let line = std::mem::take(&mut self.pending);
+        usize::from(self.push_line(&line).is_err())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn push_line(&mut self, line: &str) -> Result<(), serde_json::Error> {
+        let line = line.trim();
+        let data = match line.strip_prefix(SSE_DATA_PREFIX) {
+            Some(d) => d.trim(),
+            None if line.starts_with('{') => line, //Some servers skip the SSE framing
+            None => return Ok(()), //Empty lines, comments, event names
+        };
+        if data == SSE_DONE {
+            self.done = true;
+            return Ok(());
+        }
+
+        let chunk: OpenAIChatResponse = serde_json::from_str(data).inspect_err(|e| {
+            log_error!("push_line", "Fail to convert OpenAI stream chunk '{}'. Error: {}", data, e);
+        })?;
+
+        if !chunk.model.is_empty() {
+            self.model = chunk.model;
+        }
+        if chunk.created > 0 {
+            self.created = chunk.created;
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+
+        for choice in chunk.choices {
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason;
+            }
+            let Some(delta) = choice.delta.or(choice.message) else { continue };
+            if delta.role.is_some() {
+                self.role = delta.role;
+            }
+            if let Some(c) = delta.content {
+                self.content.push_str(&c);
+            }
+            for call in delta.tool_calls.unwrap_or_default() {
+                let idx = call.index.unwrap_or(self.tool_calls.len());
+                let stc = self.tool_calls.entry(idx).or_default();
+                if call.id.is_some() {
+                    stc.id = call.id;
+                }
+                if let Some(n) = call.function.name {
+                    stc.name.push_str(&n);
+                }
+                if let Some(a) = call.function.arguments {
+                    stc.arguments.push_str(&a);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_ai_chat_response(self) -> AIChatResponse {
+        let role = get_role(&self.role);
+        let message = if self.tool_calls.is_empty() {
+            Message::new(role, self.content)
+        } else {
+            let tools = self.tool_calls.into_values()
+                .map(|stc| get_tool_to_call(stc.id, stc.name, Some(&stc.arguments)))
+                .collect();
+            Message::new_with_tools(role, self.content, tools)
+        };
+
+        AIChatResponse {
+            model: self.model,
+            created_at: self.created.to_string(),
+            message,
+            done: self.done || self.finish_reason.is_some(),
+            done_reason: self.finish_reason,
+            total_duration: None,
+            load_duration: None,
+            prompt_eval_count: self.usage.as_ref().map(|u| u.prompt_tokens),
+            prompt_eval_duration: None,
+            eval_count: self.usage.as_ref().map(|u| u.completion_tokens),
+            eval_duration: None,
+        }
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_openai_helper {
+    use std::collections::HashMap;
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use serde_json::Value;
+
+    use crate::{ai_chat_helper::get_chat_ai_chat_request, ai_tool_to_call::ToolToCall, message::{Message, MessageRole}};
+
+    use super::{get_ai_chat_response_from_openai, get_openai_chat_request_json, OpenAIStreamAccumulator};
+
+    #[test]
+    fn test_openai_request_success() {
+        build_logger("BACHUETECH", "BT.AI_OPENAI_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut args: HashMap<String, Value> = HashMap::new();
+        args.insert("a".to_owned(), Value::from(2));
+        let context = vec![
+            Message::new_with_tools(MessageRole::ASSISTANT, "".to_owned(), vec![ToolToCall::new("do_basic_math".to_owned(), args)]),
+            Message::new_tool_result("4".to_owned(), None),
+        ];
+        let req = get_chat_ai_chat_request(&"qwen3".to_owned(), MessageRole::USER, &"The prompt".to_owned(), context, None, None, "03/27/2025", "6:45 PM", false);
+        let json_a = "{\"model\":\"qwen3\",\"messages\":[{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"id\":\"call_0\",\"type\":\"function\",\"function\":{\"name\":\"do_basic_math\",\"arguments\":\"{\\\"a\\\":2}\"}}]},{\"role\":\"tool\",\"content\":\"4\",\"tool_call_id\":\"call_0\"},{\"role\":\"user\",\"content\":\"The prompt\"}],\"stream\":false}";
+        assert_eq!(get_openai_chat_request_json(&req), json_a);
+    }
+
+    #[test]
+    fn test_openai_request_tool_call_ids() {
+        build_logger("BACHUETECH", "BT.AI_OPENAI_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let call = ToolToCall::new("do_basic_math".to_owned(), HashMap::new());
+        //Ollama history: calls and results without ids, one result with the id of an OpenAI call
+        let context = vec![
+            Message::new_with_tools(MessageRole::ASSISTANT, "".to_owned(), vec![call.clone(), call.clone()]),
+            Message::new_tool_result("r0".to_owned(), None),
+            Message::new_tool_result("r1".to_owned(), None),
+            Message::new_with_tools(MessageRole::ASSISTANT, "".to_owned(), vec![ToolToCall::new_with_id("call_x".to_owned(), "do_basic_math".to_owned(), HashMap::new()), call]),
+            Message::new_tool_result("r2".to_owned(), Some("call_x".to_owned())),
+            Message::new_tool_result("r3".to_owned(), None),
+        ];
+        let req = get_chat_ai_chat_request(&"qwen3".to_owned(), MessageRole::USER, &"The prompt".to_owned(), context, None, None, "", "", false);
+        let json: Value = serde_json::from_str(&get_openai_chat_request_json(&req)).unwrap();
+        let ids: Vec<&str> = json["messages"].as_array().unwrap().iter().filter_map(|m| m["tool_call_id"].as_str()).collect();
+        assert_eq!(ids, vec!["call_0", "call_1", "call_x", "call_2"]);
+        assert_eq!(json["messages"][3]["tool_calls"][1]["id"], "call_2");
+    }
+
+    #[test]
+    fn test_openai_response_tool_calls() {
+        build_logger("BACHUETECH", "BT.AI_OPENAI_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let body = r#"{"id":"chatcmpl-1","object":"chat.completion","created":1743115500,"model":"qwen3",
+            "choices":[{"index":0,"message":{"role":"assistant","content":null,
+                "tool_calls":[{"id":"call_abc","type":"function","function":{"name":"get_current_weather","arguments":"{\"city\":\"Paris\",\"country_code\":\"FR\"}"}}]},
+                "finish_reason":"tool_calls"}],
+            "usage":{"prompt_tokens":12,"completion_tokens":7,"total_tokens":19}}"#;
+        let resp = get_ai_chat_response_from_openai(body).unwrap();
+        assert_eq!(resp.model, "qwen3");
+        assert!(resp.done);
+        assert_eq!(resp.done_reason.unwrap(), "tool_calls");
+        assert_eq!(resp.prompt_eval_count, Some(12));
+        assert_eq!(resp.eval_count, Some(7));
+        assert_eq!(resp.message.get_role().clone(), MessageRole::ASSISTANT);
+        let tools = resp.message.get_tools().unwrap();
+        assert_eq!(tools[0].get_id().unwrap(), "call_abc");
+        assert_eq!(tools[0].get_function_name(), "get_current_weather");
+        assert_eq!(tools[0].get_raw_arguments().get("city").unwrap(), "Paris");
+    }
+
+    #[test]
+    fn test_openai_stream_accumulator() {
+        build_logger("BACHUETECH", "BT.AI_OPENAI_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut acc = OpenAIStreamAccumulator::new();
+        assert_eq!(acc.push_chunk("data: {\"model\":\"qwen3\",\"created\":1,\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"}}]}\n\ndata: {\"model\":\"qwen3\",\"choices\":[{\"index\":0,\"de"), 0);
+        assert_eq!(acc.push_chunk("lta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n"), 0);
+        assert!(acc.is_done());
+        let resp = acc.into_ai_chat_response();
+        assert_eq!(resp.message.get_content(), "Hello");
+        assert_eq!(resp.done_reason.unwrap(), "stop");
+    }
+
+    #[test]
+    fn test_openai_stream_tool_calls() {
+        build_logger("BACHUETECH", "BT.AI_OPENAI_HELPER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut acc = OpenAIStreamAccumulator::new();
+        acc.push_chunk("data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"do_basic_math\",\"arguments\":\"{\\\"a\\\":\"}}]}}]}\n");
+        acc.push_chunk("data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"3}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n");
+        assert_eq!(acc.push_chunk("data: not json\n"), 1);
+        let resp = acc.into_ai_chat_response();
+        let tools = resp.message.get_tools().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].get_id().unwrap(), "call_1");
+        assert_eq!(tools[0].get_raw_arguments().get("a").unwrap(), 3);
+    }
+}
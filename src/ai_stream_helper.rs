@@ -3,10 +3,55 @@ use std::{thread, time};
 use bt_http_utils::{stream_response::HttpStreamResponse, HttpResponse};
 use bt_logger::{log_error, log_verbose};
 
-use crate::{ai_chat_helper::AIChatResponse, ai_tool_to_call::ToolToCall, message::{Message, MessageRole}};
+use crate::{ai_chat_helper::AIChatResponse, ai_config::WireFormat, ai_openai_helper::OpenAIStreamAccumulator, ai_tool_to_call::ToolToCall, message::{Message, MessageRole}};
 
 const MAX_NUM_ERRORS: i8 = 5;
 
+///Process a stream in the wire format of the source platform
+pub async fn process_stream_by_format(streamer: HttpStreamResponse, wire_format: &WireFormat) -> HttpResponse{
+    match wire_format {
+        WireFormat::OLLAMA => process_stream(streamer).await,
+        WireFormat::OPENAI => process_openai_stream(streamer).await,
+    }
+}
+
+async fn process_openai_stream(mut streamer: HttpStreamResponse) -> HttpResponse{
+    let mut accumulator = OpenAIStreamAccumulator::new();
+    let mut streamer_remote_address: String = "0.0.0.0".to_owned();
+    let mut error_count = 0;
+
+    log_verbose!("process_openai_stream","Ready to Stream!");
+    while let Some(int_http_resp) = streamer.read_stream().await {
+        streamer_remote_address = int_http_resp.remote_address;
+        error_count += accumulator.push_chunk(&int_http_resp.body);
+        if error_count > MAX_NUM_ERRORS as usize {
+            log_error!("process_openai_stream", "Too many failures (>{}) converting JSON chunks. Abort reading/conversion.", MAX_NUM_ERRORS);
+            break
+        }
+        if accumulator.is_done() {
+            break
+        }
+    }
+    accumulator.finish();
+
+    let cr = accumulator.into_ai_chat_response();
+    log_verbose!("process_openai_stream", "Convert to JSON");
+    let j_body: String = match serde_json::to_string(&cr){
+        Ok(j) =>  j,
+        Err(e) => {
+            log_error!("process_openai_stream","Body {:?} cannot be converted to JSON. Error {}",&cr,e);
+            "".to_owned()
+        },
+    };
+
+    HttpResponse{
+        status_code: streamer.get_status(),
+        header: streamer.get_ini_header(),
+        body: j_body,
+        remote_address: streamer_remote_address,
+    }
+}
+
 pub async fn process_stream(mut streamer: HttpStreamResponse) -> HttpResponse{
     let mut streamed_content = String::new();
     let mut streamed_tools: Option<Vec<ToolToCall>> = None;
@@ -0,0 +1,253 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    error::Error,
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
+use bt_logger::{get_error, log_verbose, log_warning};
+
+use crate::{
+    ai_chat_helper::{get_chat_ai_chat_request, AIChatRequest},
+    ai_client::AIClient,
+    ai_config::SummarizerConfig,
+    ai_context::trim_context,
+    ai_token_budget::{fit_chat_request, TokenBudget, TokenCounter},
+    message::{Message, MessageRole},
+};
+
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+///Chat request whose oldest history was replaced by a summary
+pub struct SummarizedRequest {
+    pub request: AIChatRequest,
+    ///Number of history messages (SYSTEM messages excluded) replaced by the summary
+    pub summarized: usize,
+    ///History messages dropped without being summarized (the summary itself did not leave room for them)
+    pub dropped: usize,
+    ///True if the summarizer model was called for this request, false if there was nothing to summarize or the cached summary was used
+    pub summary_updated: bool,
+}
+
+///Summary of the first `covered` history messages of a conversation. `fingerprint` detects a history that no longer starts with them.
+#[derive(Debug, Clone)]
+struct CachedSummary {
+    covered: usize,
+    fingerprint: u64,
+    summary: String,
+}
+
+///Builds chat requests that keep the whole conversation in context: when the history exceeds the `ctx_max` of the platform
+///(and the token budget, if set) the messages that would be dropped are replaced by a single SYSTEM message with their summary,
+///produced by the summarizer model of the configuration. Summaries are cached per conversation and extended
+///incrementally, so the summarizer only sees the messages that overflowed since the last request.
+pub struct AISummarizer<'a> {
+    client: &'a AIClient,
+    config: SummarizerConfig,
+    token_budget: Option<(TokenBudget, Box<dyn TokenCounter>)>,
+    cache: Mutex<HashMap<String, CachedSummary>>,
+}
+
+impl<'a> AISummarizer<'a> {
+    ///Summarizer configured in the `summarizer` section of the AI configuration. None if there is none.
+    pub fn new(client: &'a AIClient) -> Option<Self> {
+        let config = client.get_ai_config().get_summarizer()?.clone();
+        Some(Self::new_with_config(client, config))
+    }
+
+    pub fn new_with_config(client: &'a AIClient, config: SummarizerConfig) -> Self {
+        Self {
+            client,
+            config,
+            token_budget: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///Also summarize the history that does not fit in a token budget (see `fit_chat_request`)
+    pub fn set_token_budget(&mut self, budget: TokenBudget, counter: Box<dyn TokenCounter>) {
+        self.token_budget = Some((budget, counter));
+    }
+
+    pub fn get_config(&self) -> &SummarizerConfig {
+        &self.config
+    }
+
+    ///Cached summary of a conversation
+    pub fn get_summary(&self, conversation_id: &str) -> Option<String> {
+        self.lock_cache().get(conversation_id).map(|c| c.summary.clone())
+    }
+
+    ///Forget the summary of a conversation (e.g. when the conversation is deleted)
+    pub fn clear(&self, conversation_id: &str) {
+        self.lock_cache().remove(conversation_id);
+    }
+
+    ///Same as `AIClient::build_chat_request`, with the overflowing history summarized instead of dropped.
+    ///`conversation_id` identifies the conversation whose summary is cached. Set `request.stream` to stream the answer.
+    pub async fn build_chat_request(&self, conversation_id: &str, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>) -> Result<SummarizedRequest, Box<dyn Error>> {
+        let overflow = self.get_overflow(platform_name, model_id, &role, message, &context);
+        let (system, history): (Vec<Message>, Vec<Message>) = context.into_iter().partition(|m| m.get_role() == MessageRole::SYSTEM);
+
+        let cached = self.lock_cache().get(conversation_id).cloned()
+            .filter(|c| c.covered <= history.len() && c.fingerprint == get_fingerprint(&history[..c.covered]));
+        let (covered, previous) = cached.map_or((0, None), |c| (c.covered, Some(c.summary)));
+
+        let mut summary_updated = false;
+        let summary = if overflow > covered {
+            let summary = self.summarize(previous.as_deref(), &history[covered..overflow]).await?;
+            self.lock_cache().insert(conversation_id.to_owned(), CachedSummary { covered: overflow, fingerprint: get_fingerprint(&history[..overflow]), summary: summary.clone() });
+            log_verbose!("build_chat_request", "Conversation {}: {} history messages summarized", conversation_id, overflow);
+            summary_updated = true;
+            Some(summary)
+        } else {
+            previous
+        };
+
+        let summarized = if summary.is_some() { overflow.max(covered) } else { 0 };
+        let mut context = system;
+        context.extend(summary.map(|s| Message::new(MessageRole::SYSTEM, format!("{} {}", SUMMARY_PREFIX, s))));
+        context.extend(history.into_iter().skip(summarized));
+
+        let (mut request, mut dropped) = self.client.build_trimmed_chat_request(platform_name, model_id, role, message, context, false);
+        if let Some((budget, counter)) = &self.token_budget {
+            dropped += fit_chat_request(&mut request, budget, counter.as_ref()).dropped;
+        }
+        if dropped > 0 {
+            log_warning!("build_chat_request", "Conversation {}: {} history messages dropped without summary", conversation_id, dropped);
+        }
+        Ok(SummarizedRequest { request, summarized, dropped, summary_updated })
+    }
+
+    ///Number of history messages (oldest first, SYSTEM messages excluded) that do not fit in the request
+    fn get_overflow(&self, platform_name: &String, model_id: &String, role: &MessageRole, message: &String, context: &[Message]) -> usize {
+        let ai_config = self.client.get_ai_config();
+        let platform = ai_config.resolve_platform(platform_name).unwrap_or(platform_name);
+        let trimmed = trim_context(context.to_vec(), ai_config.get_max_ctx_size(platform));
+        let Some((budget, counter)) = &self.token_budget else {
+            return trimmed.dropped;
+        };
+        let (mut request, _) = self.client.build_trimmed_chat_request(platform_name, model_id, role.clone(), message, trimmed.messages, false);
+        trimmed.dropped + fit_chat_request(&mut request, budget, counter.as_ref()).dropped
+    }
+
+    ///Ask the summarizer model for a summary of `messages`, on top of the previous summary
+    async fn summarize(&self, previous: Option<&str>, messages: &[Message]) -> Result<String, Box<dyn Error>> {
+        let mut prompt = self.config.instructions.clone();
+        if let Some(p) = previous {
+            prompt.push_str("\n\nSummary of the conversation so far:\n");
+            prompt.push_str(p);
+        }
+        prompt.push_str("\n\nConversation:");
+        for m in messages {
+            prompt.push_str(&format!("\n{}: {}", m.get_role().as_str(), m.get_content()));
+            if let Some(tc) = m.get_tools() && !tc.is_empty() {
+                prompt.push_str(&format!(" (tool calls: {})", serde_json::to_string(&tc).unwrap_or_default()));
+            }
+        }
+
+        //No tools and no system message of the model configuration: the answer must be the summary text
+        let model = self.client.get_ai_config().get_model(&self.config.platform, &self.config.model_id, &"".to_owned());
+        let request = get_chat_ai_chat_request(&model, MessageRole::USER, &prompt, Vec::new(), None, None, "", "", false);
+        let resp = self.client.send_chat_request(&self.config.platform, &request).await
+            .map_err(|e| get_error!("summarize", "Summarizer model {} of platform {} failed. Error: {}", &self.config.model_id, &self.config.platform, e))?;
+        let summary = resp.message.get_content().trim().to_owned();
+        if summary.is_empty() {
+            return Err(get_error!("summarize", "Summarizer model {} of platform {} returned an empty summary", &self.config.model_id, &self.config.platform).into());
+        }
+        Ok(summary)
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, HashMap<String, CachedSummary>> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn get_fingerprint(messages: &[Message]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for m in messages {
+        m.get_role().as_str().hash(&mut hasher);
+        m.get_content().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_summarizer {
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+
+    use crate::{
+        ai_client::{tests_ai_client::{get_mock_config, start_mock_server}, AIClient},
+        ai_config::SummarizerConfig,
+        ai_tools::AIToolManager,
+        message::{Message, MessageRole},
+    };
+
+    use super::AISummarizer;
+
+    fn get_summary_resp(summary: &str) -> (u16, String) {
+        (200, format!("{{\"model\":\"llama3.3:70b\",\"created_at\":\"\",\"message\":{{\"role\":\"assistant\",\"content\":\"{}\"}},\"done\":true}}", summary))
+    }
+
+    fn get_history(len: usize) -> Vec<Message> {
+        (0..len).map(|i| Message::new(if i % 2 == 0 { MessageRole::USER } else { MessageRole::ASSISTANT }, format!("m{}", i))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_summarize_overflow() {
+        build_logger("BACHUETECH", "BT.AI_SUMMARIZER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![get_summary_resp("S1"), get_summary_resp("S2")]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        //llama3.1 is configured with all the tools
+        let config = SummarizerConfig { platform: "MOCK".to_owned(), model_id: "llama3.1".to_owned(), instructions: "Summarize.".to_owned() };
+        let summarizer = AISummarizer::new_with_config(&client, config);
+        let (platform, model) = ("MOCK".to_owned(), "llama3.1".to_owned());
+
+        //Fits in ctx_max (10): nothing to summarize
+        let r = summarizer.build_chat_request("c1", &platform, &model, MessageRole::USER, &"Hi".to_owned(), get_history(10)).await.unwrap();
+        assert_eq!((r.summarized, r.dropped, r.summary_updated), (0, 0, false));
+
+        //2 messages over ctx_max
+        let r = summarizer.build_chat_request("c1", &platform, &model, MessageRole::USER, &"Hi".to_owned(), get_history(12)).await.unwrap();
+        assert_eq!((r.summarized, r.dropped, r.summary_updated), (2, 0, true));
+        assert_eq!(r.request.messages[1].get_role().clone(), MessageRole::SYSTEM);
+        assert_eq!(r.request.messages[1].get_content(), "Summary of the earlier conversation: S1");
+        assert_eq!(r.request.messages[2].get_content(), "m2");
+        assert_eq!(r.request.messages.len(), 13);
+        let summary_req = rx.recv().unwrap();
+        assert!(summary_req.contains("\"model\":\"llama3.1:8b\""));
+        //Neither the tools nor the system message of the summarizer model are sent
+        assert!(!summary_req.contains("\"tools\"") && !summary_req.contains("\"role\":\"system\""));
+        assert!(summary_req.contains("Summarize.\\n\\nConversation:\\nuser: m0\\nassistant: m1"));
+
+        //Cached: the summarizer is not called again
+        let r = summarizer.build_chat_request("c1", &platform, &model, MessageRole::USER, &"Hi".to_owned(), get_history(12)).await.unwrap();
+        assert_eq!((r.summarized, r.summary_updated), (2, false));
+
+        //Only the new overflow is sent, with the previous summary
+        let r = summarizer.build_chat_request("c1", &platform, &model, MessageRole::USER, &"Hi".to_owned(), get_history(14)).await.unwrap();
+        assert_eq!((r.summarized, r.summary_updated), (4, true));
+        assert_eq!(summarizer.get_summary("c1").unwrap(), "S2");
+        let summary_req = rx.recv().unwrap();
+        assert!(summary_req.contains("S1\\n\\nConversation:\\nuser: m2\\nassistant: m3\""));
+
+        summarizer.clear("c1");
+        assert!(summarizer.get_summary("c1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_summarizer_error() {
+        build_logger("BACHUETECH", "BT.AI_SUMMARIZER", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, _rx) = start_mock_server(vec![(500, "{}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        assert!(AISummarizer::new(&client).is_none());
+        let config = SummarizerConfig { platform: "MOCK".to_owned(), model_id: "default".to_owned(), instructions: "Summarize.".to_owned() };
+        let summarizer = AISummarizer::new_with_config(&client, config);
+        let r = summarizer.build_chat_request("c1", &"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Hi".to_owned(), get_history(12)).await;
+        assert!(r.is_err());
+        assert!(summarizer.get_summary("c1").is_none());
+    }
+}
@@ -0,0 +1,223 @@
+use std::mem;
+
+use bt_logger::{log_verbose, log_warning};
+
+use crate::{
+    ai_chat_helper::AIChatRequest,
+    ai_context::{keep_newest, split_context},
+    message::Message,
+    model_configs::ModelConfig,
+    parameter_names::{CTX_N_CTX, LLAMA_CONTEXT_LENGTH},
+};
+
+///Tokens added by the chat template around every message (role markers, separators)
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+const DEFAULT_CHARS_PER_TOKEN: f32 = 4.0;
+
+///Counts the tokens of a text. Implement it with the tokenizer of the model for exact budgets.
+pub trait TokenCounter: Send + Sync {
+    fn count_tokens(&self, text: &str) -> usize;
+
+    ///Tokens of a message: content, tool calls and the overhead of the chat template
+    fn count_message_tokens(&self, message: &Message) -> usize {
+        let tool_calls = match message.get_tools() {
+            Some(tc) if !tc.is_empty() => self.count_tokens(&serde_json::to_string(&tc).unwrap_or_default()),
+            _ => 0,
+        };
+        self.count_tokens(message.get_content()) + tool_calls + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+///Fast estimate without a tokenizer: one token every `chars_per_token` characters (4 by default), rounded up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharTokenCounter {
+    chars_per_token: f32,
+}
+
+impl CharTokenCounter {
+    pub fn new(chars_per_token: f32) -> Self {
+        Self { chars_per_token: if chars_per_token > 0.0 { chars_per_token } else { DEFAULT_CHARS_PER_TOKEN } }
+    }
+}
+
+impl Default for CharTokenCounter {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHARS_PER_TOKEN)
+    }
+}
+
+impl TokenCounter for CharTokenCounter {
+    fn count_tokens(&self, text: &str) -> usize {
+        (text.chars().count() as f32 / self.chars_per_token).ceil() as usize
+    }
+}
+
+///Tokens a chat request can use: the context length of the model minus the tokens reserved for the answer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBudget {
+    pub context_length: usize,
+    pub reserved_output: usize,
+}
+
+impl TokenBudget {
+    pub fn new(context_length: usize, reserved_output: usize) -> Self {
+        Self { context_length, reserved_output }
+    }
+
+    ///Budget of a local model: the `n_ctx` context parameter or, if it is 0 (from the model) or missing,
+    ///the `llama.context_length` read from the model file (custom model parameter). None if neither is known.
+    pub fn from_model_config(model_config: &ModelConfig, reserved_output: usize) -> Option<Self> {
+        let n_ctx = model_config.get_ctx_param(CTX_N_CTX).and_then(|v| v.as_i64()).filter(|n| *n > 0)
+            .or_else(|| model_config.get_custom_model_cfg_param(LLAMA_CONTEXT_LENGTH).and_then(|v| v.trim().parse::<i64>().ok()).filter(|n| *n > 0))?;
+        Some(Self::new(n_ctx as usize, reserved_output))
+    }
+
+    pub fn get_available(&self) -> usize {
+        self.context_length.saturating_sub(self.reserved_output)
+    }
+}
+
+///Outcome of fitting a chat request into a token budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBudgetReport {
+    pub available_tokens: usize,
+    ///Estimated tokens of the request after dropping history messages
+    pub used_tokens: usize,
+    ///Number of history messages dropped, oldest first
+    pub dropped: usize,
+}
+
+impl TokenBudgetReport {
+    ///False if the system messages, tools and new prompt alone exceed the budget
+    pub fn fits(&self) -> bool {
+        self.used_tokens <= self.available_tokens
+    }
+}
+
+///Drop the oldest history messages of a chat request until system messages + tools + history + new prompt fit in the budget.
+///The prompt is the group of the last message: the message itself or, if it is a TOOL result (e.g. in a tool loop), the
+///assistant tool calls and all their results. System messages and the prompt are always kept and tool call groups are
+///never split (see `trim_context`).
+pub fn fit_chat_request(ai_request: &mut AIChatRequest, budget: &TokenBudget, counter: &dyn TokenCounter) -> TokenBudgetReport {
+    let available_tokens = budget.get_available();
+    let count = |msgs: &[Message]| msgs.iter().map(|m| counter.count_message_tokens(m)).sum::<usize>();
+
+    let messages = mem::take(&mut ai_request.messages);
+    let total = messages.len();
+    let (system, mut groups) = split_context(messages);
+    let prompt = groups.pop().unwrap_or_default();
+    let tools_tokens = ai_request.tools.as_ref().map_or(0, |t| counter.count_tokens(&serde_json::to_string(t).unwrap_or_default()));
+    let fixed_tokens = tools_tokens + count(&system) + count(&prompt);
+
+    let history = keep_newest(groups, available_tokens.saturating_sub(fixed_tokens), count);
+    let report = TokenBudgetReport {
+        available_tokens,
+        used_tokens: fixed_tokens + count(&history),
+        dropped: total - system.len() - prompt.len() - history.len(),
+    };
+
+    ai_request.messages = system;
+    ai_request.messages.extend(history);
+    ai_request.messages.extend(prompt);
+
+    if !report.fits() {
+        log_warning!("fit_chat_request", "System messages, tools and prompt need {} tokens, more than the {} available", fixed_tokens, available_tokens);
+    } else if report.dropped > 0 {
+        log_verbose!("fit_chat_request", "{} history messages dropped to fit {} tokens ({} used)", report.dropped, available_tokens, report.used_tokens);
+    }
+    report
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_token_budget {
+    use std::collections::HashMap;
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use yaml_rust2::YamlLoader;
+
+    use crate::{
+        ai_chat_helper::get_chat_ai_chat_request,
+        ai_tool_to_call::ToolToCall,
+        message::{Message, MessageRole},
+        model_configs::ModelConfigs,
+        parameter_names::LLAMA_CONTEXT_LENGTH,
+    };
+
+    use super::{fit_chat_request, CharTokenCounter, TokenBudget, TokenCounter};
+
+    #[test]
+    fn test_char_token_counter() {
+        let counter = CharTokenCounter::default();
+        assert_eq!(counter.count_tokens(""), 0);
+        assert_eq!(counter.count_tokens("abcde"), 2);
+        assert_eq!(counter.count_tokens("ñandú"), 2);
+        assert_eq!(CharTokenCounter::new(0.0), counter);
+        assert_eq!(counter.count_message_tokens(&Message::new(MessageRole::USER, "abcd".to_owned())), 5);
+    }
+
+    #[test]
+    fn test_fit_chat_request() {
+        build_logger("BACHUETECH", "BT.AI_TOKEN_BUDGET", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        //Every message is 10 tokens (24 characters + overhead 4)
+        let text = |i: usize| format!("{:024}", i);
+        let context: Vec<Message> = (0..6).map(|i| Message::new(MessageRole::USER, text(i))).collect();
+        let mut req = get_chat_ai_chat_request(&"llama3.1".to_owned(), MessageRole::USER, &text(99), context, None, None, "", "", false);
+        req.messages.insert(0, Message::new(MessageRole::SYSTEM, text(100)));
+
+        //System + prompt: 20 tokens. 35 more tokens: 3 history messages
+        let report = fit_chat_request(&mut req, &TokenBudget::new(100, 45), &CharTokenCounter::default());
+        assert_eq!(report.dropped, 3);
+        assert_eq!(report.used_tokens, 50);
+        assert!(report.fits());
+        assert_eq!(req.messages.len(), 5);
+        assert_eq!(req.messages[1].get_content(), &text(3));
+        assert_eq!(req.messages[4].get_content(), &text(99));
+
+        let report = fit_chat_request(&mut req, &TokenBudget::new(15, 0), &CharTokenCounter::default());
+        assert!(!report.fits());
+        assert_eq!(req.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_fit_chat_request_tool_result() {
+        build_logger("BACHUETECH", "BT.AI_TOKEN_BUDGET", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let text = |i: usize| format!("{:024}", i);
+        let call = Message::new_with_tools(MessageRole::ASSISTANT, text(1), vec![ToolToCall::new("do_basic_math".to_owned(), HashMap::new())]);
+        //In a tool loop the last message is a TOOL result: its tool call group is the prompt
+        let context = vec![Message::new(MessageRole::USER, text(0)), call];
+        let mut req = get_chat_ai_chat_request(&"llama3.1".to_owned(), MessageRole::TOOL, &text(2), context, None, None, "", "", false);
+
+        let report = fit_chat_request(&mut req, &TokenBudget::new(10, 0), &CharTokenCounter::default());
+        assert!(!report.fits());
+        assert_eq!(report.dropped, 1);
+        assert_eq!(req.messages.len(), 2);
+        assert!(req.messages[0].has_tool_calls());
+        assert_eq!(req.messages[1].get_role().clone(), MessageRole::TOOL);
+    }
+
+    #[test]
+    fn test_budget_from_model_config() {
+        let yml = YamlLoader::load_from_str("
+dev:
+  models:
+    - model_id: fixed
+      ctx_params:
+        - param_id: n_ctx
+          param_value: 8192
+    - model_id: from_model
+      ctx_params:
+        - param_id: n_ctx
+          param_value: 0
+").unwrap();
+        let cfgs = ModelConfigs::new_from_yaml(&yml[0], "dev").unwrap();
+        assert_eq!(TokenBudget::from_model_config(&cfgs.get_model_configs("fixed").unwrap(), 1024).unwrap().get_available(), 7168);
+
+        let mut from_model = cfgs.get_model_configs("from_model").unwrap();
+        assert!(TokenBudget::from_model_config(&from_model, 1024).is_none());
+        from_model.set_custom_model_cfg_param(LLAMA_CONTEXT_LENGTH, "131072".to_owned());
+        assert_eq!(TokenBudget::from_model_config(&from_model, 1024).unwrap().context_length, 131072);
+    }
+}
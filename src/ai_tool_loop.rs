@@ -0,0 +1,241 @@
+use std::error::Error;
+
+use bt_logger::{log_trace, log_warning};
+
+use crate::{
+    ai_chat_helper::AIChatResponse,
+    ai_client::AIClient,
+    ai_tool_registry::AIToolRegistry,
+    ai_tool_validation::{get_violations_message, validate_tool_call, ToolCallViolation},
+    message::{Message, MessageRole},
+};
+
+const DEFAULT_MAX_TOOL_ITERATIONS: usize = 5;
+const NOT_EXECUTED_RESULT: &str = "Error: not executed. The tool iteration limit was reached";
+
+///Outcome of a chat with automatic tool calling
+#[derive(Debug)]
+pub struct AIToolLoopResult {
+    ///Last response received from the AI model
+    pub response: AIChatResponse,
+    ///New messages of the turn (the prompt, assistant tool calls, tool results and the final answer) to append to the history.
+    ///If the iteration limit is reached, the last tool calls are answered with a "not executed" TOOL result.
+    pub messages: Vec<Message>,
+    ///Number of chat requests sent
+    pub iterations: usize,
+    ///Number of context messages dropped to fit the `ctx_max` of the platform
+    pub dropped_messages: usize,
+    ///True if the loop stopped because the limit was reached while the model still requested tools
+    pub max_iterations_reached: bool,
+}
+
+///Sends a chat, executes the `tool_calls` returned by the model with the registered handlers,
+///appends the results as TOOL messages and repeats until the model answers without tool calls.
+///Tool calls that do not match the tool definition, or call a tool the model is not configured to use, are not executed;
+///the violations are sent back instead.
+pub struct AIToolLoop<'a> {
+    client: &'a AIClient,
+    registry: &'a AIToolRegistry,
+    max_iterations: usize,
+}
+
+impl<'a> AIToolLoop<'a> {
+    pub fn new(client: &'a AIClient, registry: &'a AIToolRegistry) -> Self {
+        for n in registry.get_undefined(client.get_tool_manager()) {
+            log_warning!("new", "Tool handler {} has no tool definition. The AI model will not be offered this tool", n);
+        }
+        Self {
+            client,
+            registry,
+            max_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
+        }
+    }
+
+    ///Maximum number of chat requests per turn (minimum 1)
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations.max(1);
+    }
+
+    pub fn get_max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    pub async fn run(&self, platform_name: &String, model_id: &String, role: MessageRole, message: &String, context: Vec<Message>) -> Result<AIToolLoopResult, Box<dyn Error>> {
+        let context_len = context.len();
+        let (mut ai_request, dropped_messages) = self.client.build_trimmed_chat_request(platform_name, model_id, role, message, context, false);
+        //Keep the new prompt (last message) as part of the turn
+        let turn_start = ai_request.messages.len() - 1;
+        log_trace!("run", "Starting tool loop on top of {} context messages", context_len);
+        //Only the tools of the model can be called, even if other tools are defined and have handlers
+        let model_tools = self.client.get_tool_manager().get_tools(platform_name, model_id).unwrap_or_default();
+
+        let mut iteration = 0;
+        loop {
+            iteration += 1;
+            let response = self.client.send_chat_request(platform_name, &ai_request).await?;
+            let tool_calls = response.message.get_tools().unwrap_or_default();
+            ai_request.messages.push(response.message.clone());
+
+            if tool_calls.is_empty() || iteration >= self.max_iterations {
+                let max_iterations_reached = !tool_calls.is_empty();
+                if max_iterations_reached {
+                    log_warning!("run", "Maximum number of tool iterations ({}) reached. {} tool calls were not executed", self.max_iterations, tool_calls.len());
+                    //Answer every pending call so the history can be sent again (OpenAI rejects tool calls without results)
+                    for tc in &tool_calls {
+                        ai_request.messages.push(Message::new_tool_result(NOT_EXECUTED_RESULT.to_owned(), tc.get_id().cloned()));
+                    }
+                }
+                return Ok(AIToolLoopResult {
+                    response,
+                    messages: ai_request.messages.split_off(turn_start),
+                    iterations: iteration,
+                    dropped_messages,
+                    max_iterations_reached,
+                });
+            }
+
+            for tc in tool_calls {
+                let violations = match model_tools.iter().find(|t| t.get_function_name() == tc.get_function_name()) {
+                    Some(tool) => validate_tool_call(tool, &tc),
+                    None => vec![ToolCallViolation::UnknownTool(tc.get_function_name().clone())],
+                };
+                if !violations.is_empty() {
+                    log_warning!("run", "Invalid call to tool {}: {:?}", tc.get_function_name(), &violations);
+                    ai_request.messages.push(get_violations_message(&tc, &violations));
+                    continue;
+                }
+                let content = match self.registry.call(&tc).await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        log_warning!("run", "Tool {} failed. Error: {}", tc.get_function_name(), &e);
+                        format!("Error: {}", e)
+                    }
+                };
+                ai_request.messages.push(Message::new_tool_result(content, tc.get_id().cloned()));
+            }
+        }
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_tool_loop {
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+
+    use crate::{
+        ai_client::{tests_ai_client::{get_mock_config, start_mock_server}, AIClient},
+        ai_tool_registry::AIToolRegistry,
+        ai_tools::AIToolManager,
+        message::MessageRole,
+    };
+
+    use super::AIToolLoop;
+
+    const TOOL_CALL_RESP: &str = "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"do_basic_math\",\"arguments\":{\"a\":2,\"op\":\"+\",\"b\":3}}}]},\"done\":true}";
+    const FINAL_RESP: &str = "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"The result is 5\"},\"done\":true}";
+
+    fn get_registry() -> AIToolRegistry {
+        let mut reg = AIToolRegistry::new();
+        reg.register("do_basic_math", |t| {
+            let args = t.get_raw_arguments();
+            Ok((args["a"].as_f64().unwrap() + args["b"].as_f64().unwrap()).to_string())
+        });
+        reg
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_success() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_LOOP", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, rx) = start_mock_server(vec![(200, TOOL_CALL_RESP.to_owned()), (200, FINAL_RESP.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let reg = get_registry();
+        let result = AIToolLoop::new(&client, &reg).run(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"2+3?".to_owned(), Vec::new()).await.unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert_eq!(result.dropped_messages, 0);
+        assert!(!result.max_iterations_reached);
+        assert_eq!(result.response.message.get_content(), "The result is 5");
+        //user, assistant tool call, tool result, final answer
+        assert_eq!(result.messages.len(), 4);
+        assert_eq!(result.messages[2].get_role().clone(), MessageRole::TOOL);
+        assert_eq!(result.messages[2].get_content(), "5");
+
+        rx.recv().unwrap();
+        let second_req = rx.recv().unwrap();
+        assert!(second_req.contains("{\"role\":\"tool\",\"content\":\"5\"}"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_max_iterations() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_LOOP", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, _rx) = start_mock_server(vec![(200, TOOL_CALL_RESP.to_owned()), (200, TOOL_CALL_RESP.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let reg = get_registry();
+        let mut tool_loop = AIToolLoop::new(&client, &reg);
+        tool_loop.set_max_iterations(2);
+        let result = tool_loop.run(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"2+3?".to_owned(), Vec::new()).await.unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert!(result.max_iterations_reached);
+        assert!(result.response.message.get_tools().is_some());
+        //user, tool call, result, tool call, not executed result
+        assert_eq!(result.messages.len(), 5);
+        assert_eq!(result.messages[4].get_role().clone(), MessageRole::TOOL);
+        assert!(result.messages[4].get_content().contains("not executed"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_max_iterations_replay() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_LOOP", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, _rx) = start_mock_server(vec![(200, TOOL_CALL_RESP.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let reg = get_registry();
+        let mut tool_loop = AIToolLoop::new(&client, &reg);
+        tool_loop.set_max_iterations(1);
+        let result = tool_loop.run(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"2+3?".to_owned(), Vec::new()).await.unwrap();
+        assert!(result.max_iterations_reached);
+
+        //Replay the turn as context of an OpenAI format platform: every tool call has its result
+        let (port, rx) = start_mock_server(vec![(200, "{\"model\":\"llama3.1:8b\",\"created\":0,\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"Sorry\"},\"finish_reason\":\"stop\"}]}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "openai")));
+        client.chat(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"Go on".to_owned(), result.messages).await.unwrap();
+        let req = rx.recv().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&req[req.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        let call_ids: Vec<&serde_json::Value> = messages.iter().filter_map(|m| m["tool_calls"].as_array()).flatten().map(|c| &c["id"]).collect();
+        let result_ids: Vec<&serde_json::Value> = messages.iter().filter(|m| m["role"] == "tool").map(|m| &m["tool_call_id"]).collect();
+        assert_eq!(call_ids.len(), 1);
+        assert_eq!(call_ids, result_ids);
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_invalid_call() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_LOOP", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let bad_call = TOOL_CALL_RESP.replace("\"b\":3", "\"c\":3");
+        let (port, rx) = start_mock_server(vec![(200, bad_call), (200, FINAL_RESP.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let reg = get_registry();
+        let result = AIToolLoop::new(&client, &reg).run(&"MOCK".to_owned(), &"llama3.1".to_owned(), MessageRole::USER, &"2+3?".to_owned(), Vec::new()).await.unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert!(result.messages[2].get_content().contains("Required argument 'b' is missing"));
+        rx.recv().unwrap();
+        assert!(rx.recv().unwrap().contains("Argument 'c' is not defined for this tool"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_tool_not_allowed() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_LOOP", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let (port, _rx) = start_mock_server(vec![(200, TOOL_CALL_RESP.to_owned()), (200, FINAL_RESP.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let reg = get_registry();
+        //The default model has no tools: do_basic_math is defined and has a handler but is not executed
+        let result = AIToolLoop::new(&client, &reg).run(&"MOCK".to_owned(), &"default".to_owned(), MessageRole::USER, &"2+3?".to_owned(), Vec::new()).await.unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert_eq!(result.messages[2].get_role().clone(), MessageRole::TOOL);
+        assert!(result.messages[2].get_content().contains("Tool 'do_basic_math' does not exist"), "{}", result.messages[2].get_content());
+    }
+}
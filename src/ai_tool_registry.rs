@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc};
+
+use bt_logger::{log_verbose, log_warning};
+
+use crate::{ai_tool_to_call::ToolToCall, ai_tools::AIToolManager};
+
+///Result of a tool handler: the content sent back to the AI model or an error description
+pub type ToolResult = Result<String, String>;
+pub type ToolHandlerFuture = Pin<Box<dyn Future<Output = ToolResult> + Send>>;
+type ToolHandler = Arc<dyn Fn(ToolToCall) -> ToolHandlerFuture + Send + Sync>;
+
+///Rust handlers for the tools (functions) the AI models can call, by function name
+#[derive(Clone, Default)]
+pub struct AIToolRegistry {
+    handlers: HashMap<String, ToolHandler>,
+}
+
+impl fmt::Debug for AIToolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AIToolRegistry").field("handlers", &self.get_names()).finish()
+    }
+}
+
+impl AIToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Register a synchronous handler. Returns true if a handler with the same name was replaced.
+    pub fn register<F>(&mut self, function_name: &str, handler: F) -> bool
+    where
+        F: Fn(&ToolToCall) -> ToolResult + Send + Sync + 'static,
+    {
+        let h: ToolHandler = Arc::new(move |ttc: ToolToCall| {
+            let r = handler(&ttc);
+            Box::pin(async move { r })
+        });
+        self.insert(function_name, h)
+    }
+
+    ///Register an async handler. Returns true if a handler with the same name was replaced.
+    pub fn register_async<F, Fut>(&mut self, function_name: &str, handler: F) -> bool
+    where
+        F: Fn(ToolToCall) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ToolResult> + Send + 'static,
+    {
+        let h: ToolHandler = Arc::new(move |ttc: ToolToCall| Box::pin(handler(ttc)));
+        self.insert(function_name, h)
+    }
+
+    fn insert(&mut self, function_name: &str, handler: ToolHandler) -> bool {
+        let replaced = self.handlers.insert(function_name.trim().to_owned(), handler).is_some();
+        if replaced {
+            log_warning!("register", "Handler for tool {} was already registered. Replacing it", function_name);
+        }
+        replaced
+    }
+
+    pub fn unregister(&mut self, function_name: &str) -> bool {
+        self.handlers.remove(function_name).is_some()
+    }
+
+    pub fn contains(&self, function_name: &str) -> bool {
+        self.handlers.contains_key(function_name)
+    }
+
+    ///Registered function names, sorted
+    pub fn get_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.handlers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    ///Registered function names without a tool definition in the tool manager
+    pub fn get_undefined(&self, tool_manager: &AIToolManager) -> Vec<String> {
+        self.get_names().into_iter().filter(|n| tool_manager.get_tool(n).is_none()).collect()
+    }
+
+    ///Execute the handler of a tool call
+    pub async fn call(&self, tool: &ToolToCall) -> ToolResult {
+        match self.handlers.get(tool.get_function_name()) {
+            Some(h) => {
+                log_verbose!("call", "Calling tool {}", tool.get_function_name());
+                h(tool.clone()).await
+            }
+            None => Err(format!("Tool {} is not available", tool.get_function_name())),
+        }
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_tool_registry {
+    use std::collections::HashMap;
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+    use serde_json::Value;
+
+    use crate::{ai_config::AIConfig, ai_tool_to_call::ToolToCall, ai_tools::AIToolManager};
+
+    use super::AIToolRegistry;
+
+    fn get_math_call(a: i64, b: i64) -> ToolToCall {
+        let mut args: HashMap<String, Value> = HashMap::new();
+        args.insert("a".to_owned(), Value::from(a));
+        args.insert("b".to_owned(), Value::from(b));
+        ToolToCall::new("do_basic_math".to_owned(), args)
+    }
+
+    #[tokio::test]
+    async fn test_registry_sync_async() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_REGISTRY", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut reg = AIToolRegistry::new();
+        assert!(!reg.register("do_basic_math", |t| {
+            let args = t.get_raw_arguments();
+            Ok((args["a"].as_i64().unwrap() + args["b"].as_i64().unwrap()).to_string())
+        }));
+        assert!(!reg.register_async("get_current_weather", |_t| async { Ok("Sunny".to_owned()) }));
+        assert_eq!(reg.get_names(), vec!["do_basic_math", "get_current_weather"]);
+        assert_eq!(reg.call(&get_math_call(2, 3)).await.unwrap(), "5");
+        assert_eq!(reg.call(&ToolToCall::new("get_current_weather".to_owned(), HashMap::new())).await.unwrap(), "Sunny");
+    }
+
+    #[tokio::test]
+    async fn test_registry_unknown_tool() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_REGISTRY", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut reg = AIToolRegistry::new();
+        reg.register("do_basic_math", |_t| Err("Division by zero".to_owned()));
+        assert_eq!(reg.call(&get_math_call(1, 0)).await.unwrap_err(), "Division by zero");
+        assert!(reg.unregister("do_basic_math"));
+        assert!(reg.call(&get_math_call(1, 0)).await.unwrap_err().contains("not available"));
+    }
+
+    #[test]
+    fn test_registry_undefined() {
+        build_logger("BACHUETECH", "BT.AI_TOOL_REGISTRY", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let tm = AIToolManager::new_with_config(AIConfig::new("dev").unwrap());
+        let mut reg = AIToolRegistry::new();
+        reg.register("do_basic_math", |_t| Ok("".to_owned()));
+        reg.register("do_nothing", |_t| Ok("".to_owned()));
+        assert_eq!(reg.get_undefined(&tm), vec!["do_nothing"]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ai_tools::{Tool, ToolParamProperty};
+
+///Used by the code generated by `#[derive(AiTool)]`
+#[doc(hidden)]
+pub mod __private {
+    pub use serde_json::Value;
+    pub use std::collections::HashMap;
+}
+
+///Types that are tools the AI models can call: an arguments struct whose fields are the tool parameters.
+///Use `#[derive(AiTool)]` (feature `derive`) to implement it from the struct and its doc comments.
+pub trait AiTool {
+    fn get_tool() -> Tool;
+}
+
+///JSON Schema of a Rust type used as a tool parameter
+pub trait ToolParamSchema {
+    fn get_param_schema() -> ToolParamProperty;
+
+    ///False for parameters the AI model can omit (`Option<T>`)
+    fn is_required() -> bool {
+        true
+    }
+}
+
+macro_rules! impl_param_schema {
+    ($type_name:literal, $($t:ty),+) => {
+        $(
+            impl ToolParamSchema for $t {
+                fn get_param_schema() -> ToolParamProperty {
+                    ToolParamProperty::new($type_name)
+                }
+            }
+        )+
+    };
+}
+
+impl_param_schema!("string", String, &str, char);
+impl_param_schema!("number", f32, f64);
+impl_param_schema!("integer", i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_param_schema!("boolean", bool);
+impl_param_schema!("object", serde_json::Map<String, serde_json::Value>);
+
+impl<T: ToolParamSchema> ToolParamSchema for Option<T> {
+    fn get_param_schema() -> ToolParamProperty {
+        T::get_param_schema()
+    }
+
+    fn is_required() -> bool {
+        false
+    }
+}
+
+impl<T: ToolParamSchema> ToolParamSchema for Vec<T> {
+    fn get_param_schema() -> ToolParamProperty {
+        let mut p = ToolParamProperty::new("array");
+        p.set_items(T::get_param_schema());
+        p
+    }
+}
+
+impl<T: ToolParamSchema> ToolParamSchema for HashMap<String, T> {
+    fn get_param_schema() -> ToolParamProperty {
+        ToolParamProperty::new("object")
+    }
+}
+
+impl<T: ToolParamSchema> ToolParamSchema for BTreeMap<String, T> {
+    fn get_param_schema() -> ToolParamProperty {
+        ToolParamProperty::new("object")
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_tool_schema {
+    use std::collections::HashMap;
+
+    use bt_ai_core_derive::AiTool;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use crate::{ai_tool_to_call::ToolToCall, ai_tool_validation::{validate_tool_call, validate_tool_definitions}};
+
+    use super::AiTool;
+
+    /// Item of an order
+    #[derive(AiTool, Deserialize)]
+    #[allow(dead_code)]
+    struct OrderItem {
+        /// Product code
+        sku: String,
+        /// Number of units
+        qty: u32,
+    }
+
+    /// Do basic math operations
+    #[derive(AiTool, Deserialize)]
+    #[allow(dead_code)]
+    struct DoBasicMath {
+        /// The first numeric operand
+        a: f64,
+        /// The operation to perform
+        #[ai_tool(enum_values("+", "-", "*", "/", "^"))]
+        op: String,
+        /// The second numeric operand
+        b: f64,
+        /// Round the result to this number
+        /// of decimals
+        decimals: Option<u8>,
+    }
+
+    /// Add items to an order
+    #[derive(AiTool, Deserialize)]
+    #[ai_tool(name = "add_items")]
+    #[allow(dead_code)]
+    struct AddItemsArgs {
+        /// Items to add
+        items: Vec<OrderItem>,
+        #[serde(rename = "orderId")]
+        order_id: i64,
+    }
+
+    /// Get the HTTP status of a URL
+    #[derive(AiTool, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    #[allow(dead_code)]
+    struct GetHTTPStatus {
+        /// URL to check
+        target_url: String,
+        /// Seconds to wait
+        #[serde(default)]
+        timeout_secs: u32,
+        #[ai_tool(rename = "followRedirects")]
+        #[serde(rename = "followRedirects", default)]
+        follow_redirects: bool,
+        #[serde(skip)]
+        attempts: u32,
+    }
+
+    #[test]
+    fn test_derive_tool() {
+        let tool = DoBasicMath::get_tool();
+        assert_eq!(tool.get_function_name(), "do_basic_math");
+        assert_eq!(tool.get_description(), "Do basic math operations");
+        assert_eq!(tool.get_parameters().get_required(), &vec!["a".to_owned(), "op".to_owned(), "b".to_owned()]);
+        let props = tool.get_parameters().get_properties();
+        assert_eq!(props["decimals"].get_description().unwrap(), "Round the result to this number of decimals");
+        assert_eq!(props["op"].get_enum().unwrap().len(), 5);
+        assert!(validate_tool_definitions(std::slice::from_ref(&tool)).is_empty());
+
+        let args: HashMap<String, Value> = serde_json::from_str("{\"a\": 2, \"op\": \"%\", \"b\": 3}").unwrap();
+        assert_eq!(validate_tool_call(&tool, &ToolToCall::new("do_basic_math".to_owned(), args)).len(), 1);
+    }
+
+    #[test]
+    fn test_derive_tool_nested() {
+        let tool = AddItemsArgs::get_tool();
+        assert_eq!(tool.get_function_name(), "add_items");
+        let props = tool.get_parameters().get_properties();
+        assert!(props.contains_key("orderId"));
+        let item = props["items"].get_items().unwrap();
+        assert_eq!(item.get_description().unwrap(), "Item of an order");
+        assert_eq!(item.get_required().unwrap(), &vec!["sku".to_owned(), "qty".to_owned()]);
+
+        let args: HashMap<String, Value> = serde_json::from_str("{\"items\": [{\"sku\": \"A-1\", \"qty\": 2}], \"orderId\": 7}").unwrap();
+        let call = ToolToCall::new("add_items".to_owned(), args);
+        assert!(validate_tool_call(&tool, &call).is_empty());
+        assert_eq!(call.parse_arguments::<AddItemsArgs>().unwrap().order_id, 7);
+    }
+
+    #[test]
+    fn test_derive_tool_serde_attrs() {
+        let tool = GetHTTPStatus::get_tool();
+        //Acronyms are kept together in snake case
+        assert_eq!(tool.get_function_name(), "get_http_status");
+        let props = tool.get_parameters().get_properties();
+        let mut names: Vec<&String> = props.keys().collect();
+        names.sort();
+        //Parameters are named as serde parses them; skipped fields are not parameters
+        assert_eq!(names, vec!["followRedirects", "targetUrl", "timeoutSecs"]);
+        //serde default makes a parameter optional
+        assert_eq!(tool.get_parameters().get_required(), &vec!["targetUrl".to_owned()]);
+
+        let args: HashMap<String, Value> = serde_json::from_str("{\"targetUrl\": \"https://example.com\"}").unwrap();
+        let call = ToolToCall::new("get_http_status".to_owned(), args);
+        assert!(validate_tool_call(&tool, &call).is_empty());
+        let parsed = call.parse_arguments::<GetHTTPStatus>().unwrap();
+        assert_eq!((parsed.target_url.as_str(), parsed.timeout_secs), ("https://example.com", 0));
+    }
+
+    #[test]
+    fn test_derive_tool_round_trip() {
+        let tool = GetHTTPStatus::get_tool();
+        //A call that sets every parameter of the schema parses every field
+        let args: HashMap<String, Value> = tool.get_parameters().get_properties().keys()
+            .map(|k| (k.clone(), match k.as_str() {
+                "targetUrl" => json!("https://example.com"),
+                "timeoutSecs" => json!(30),
+                "followRedirects" => json!(true),
+                other => panic!("Unexpected parameter {}", other),
+            }))
+            .collect();
+        assert_eq!(args.len(), 3);
+        let call = ToolToCall::new("get_http_status".to_owned(), args);
+        assert!(validate_tool_call(&tool, &call).is_empty());
+        let parsed = call.parse_arguments::<GetHTTPStatus>().unwrap();
+        assert_eq!((parsed.target_url.as_str(), parsed.timeout_secs, parsed.follow_redirects), ("https://example.com", 30, true));
+    }
+}
@@ -1,11 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, fmt};
 
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::ai_tool_validation::get_value_type;
 
 ///Tools Returned by AI Model that the application needs to call to return an answer to the AI model.
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ToolToCall{
+    ///Id assigned by the platform to the call (OpenAI compatible platforms). Ollama does not send it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     function: FunctionToCall
 }
 
@@ -15,6 +20,36 @@ pub struct FunctionToCall{
     arguments: HashMap<String,Value>,
 }
 
+///Error extracting the arguments of a tool call. Every variant names the offending argument (field).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolArgumentError{
+    Missing(String),
+    InvalidType{ field: String, expected: String, found: String },
+    Invalid{ field: String, message: String },
+}
+
+impl ToolArgumentError {
+    pub fn get_field(&self) -> &String{
+        match self {
+            ToolArgumentError::Missing(field) => field,
+            ToolArgumentError::InvalidType { field, .. } => field,
+            ToolArgumentError::Invalid { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for ToolArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolArgumentError::Missing(field) => write!(f, "Missing argument '{}'", field),
+            ToolArgumentError::InvalidType { field, expected, found } => write!(f, "Argument '{}' must be {} but it is {}", field, expected, found),
+            ToolArgumentError::Invalid { field, message } => write!(f, "Invalid argument '{}': {}", field, message),
+        }
+    }
+}
+
+impl Error for ToolArgumentError {}
+
 impl ToolToCall{
     pub fn new(function_name: String, function_args: HashMap<String, Value>) -> Self{
         let ftc = FunctionToCall{
@@ -23,14 +58,31 @@ impl ToolToCall{
         };
 
         Self{
+            id: None,
             function: ftc,
         }
     }
 
+    pub fn new_with_id(call_id: String, function_name: String, function_args: HashMap<String, Value>) -> Self{
+        let mut ttc = Self::new(function_name, function_args);
+        ttc.id = Some(call_id);
+        ttc
+    }
+
+    pub fn get_id(&self) -> Option<&String>{
+        self.id.as_ref()
+    }
+
+    ///Raw JSON arguments as returned by the AI Model
+    pub fn get_raw_arguments(&self) -> &HashMap<String,Value>{
+        &self.function.arguments
+    }
+
     pub fn get_function_name(&self) -> &String{
         &self.function.name
     }
 
+    ///Arguments as JSON text (strings keep their quotes). Use the typed accessors (`get_str`, `get_f64`, ...) or `parse_arguments` instead.
     pub fn get_arguments(&self) -> HashMap<String,String>{
         //&self.function.arguments
         //ToDo: Need a more elegant solution.
@@ -42,6 +94,58 @@ impl ToolToCall{
         }
         output
     }
+
+    pub fn get_value(&self, arg_name: &str) -> Option<&Value>{
+        self.function.arguments.get(arg_name)
+    }
+
+    fn get_typed<'a, T>(&'a self, arg_name: &str, expected: &str, convert: impl Fn(&'a Value) -> Option<T>) -> Result<T, ToolArgumentError>{
+        let value = self.get_value(arg_name).ok_or(ToolArgumentError::Missing(arg_name.to_owned()))?;
+        convert(value).ok_or(ToolArgumentError::InvalidType {
+            field: arg_name.to_owned(),
+            expected: expected.to_owned(),
+            found: get_value_type(value).to_owned(),
+        })
+    }
+
+    pub fn get_str(&self, arg_name: &str) -> Result<&str, ToolArgumentError>{
+        self.get_typed(arg_name, "a string", |v| v.as_str())
+    }
+
+    pub fn get_f64(&self, arg_name: &str) -> Result<f64, ToolArgumentError>{
+        self.get_typed(arg_name, "a number", |v| v.as_f64())
+    }
+
+    pub fn get_i64(&self, arg_name: &str) -> Result<i64, ToolArgumentError>{
+        self.get_typed(arg_name, "an integer", |v| v.as_i64())
+    }
+
+    pub fn get_u64(&self, arg_name: &str) -> Result<u64, ToolArgumentError>{
+        self.get_typed(arg_name, "a non negative integer", |v| v.as_u64())
+    }
+
+    pub fn get_bool(&self, arg_name: &str) -> Result<bool, ToolArgumentError>{
+        self.get_typed(arg_name, "a boolean", |v| v.as_bool())
+    }
+
+    pub fn get_array(&self, arg_name: &str) -> Result<&Vec<Value>, ToolArgumentError>{
+        self.get_typed(arg_name, "an array", |v| v.as_array())
+    }
+
+    ///Deserialize all the arguments into a user struct
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> Result<T, ToolArgumentError>{
+        let args: Map<String, Value> = self.function.arguments.clone().into_iter().collect();
+        serde_path_to_error::deserialize(Value::Object(args)).map_err(|e| {
+            let field = e.path().to_string();
+            let message = e.inner().to_string();
+            //serde reports missing fields at the parent level: "missing field `name`"
+            match message.strip_prefix("missing field `").and_then(|m| m.split('`').next()) {
+                Some(missing) if field == "." => ToolArgumentError::Missing(missing.to_owned()),
+                Some(missing) => ToolArgumentError::Missing(format!("{}.{}", field, missing)),
+                None => ToolArgumentError::Invalid { field, message },
+            }
+        })
+    }
 }
 
 
@@ -56,7 +160,9 @@ mod tests_tool_to_call{
     use bt_logger::{build_logger, LogLevel, LogTarget};
     use serde_json::Value;
 
-    use crate::ai_tool_to_call::{FunctionToCall, ToolToCall};
+    use serde::Deserialize;
+
+    use crate::ai_tool_to_call::{FunctionToCall, ToolArgumentError, ToolToCall};
 
 
 #[test]
@@ -69,6 +175,7 @@ fn test_tool_to_call(){
         arguments: arg,
     };
     let ttc = ToolToCall{
+        id: None,
         function: ftc,
     };
 
@@ -90,4 +197,41 @@ fn test_tool_to_call_new(){
     
 }
 
-}
\ No newline at end of file
+#[test]
+fn test_tool_to_call_typed(){
+    build_logger("BACHUETECH", "BT.AI_TOOL2CALL", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+    let args: HashMap<String, Value> = serde_json::from_str("{\"a\": 2.5, \"n\": 3, \"op\": \"+\", \"exact\": true}").unwrap();
+    let ttc = ToolToCall::new("do_basic_math".to_owned(), args);
+
+    assert_eq!(ttc.get_str("op").unwrap(), "+");
+    assert_eq!(ttc.get_f64("a").unwrap(), 2.5);
+    assert_eq!(ttc.get_f64("n").unwrap(), 3.0);
+    assert_eq!(ttc.get_i64("n").unwrap(), 3);
+    assert!(ttc.get_bool("exact").unwrap());
+    assert_eq!(ttc.get_str("b").unwrap_err(), ToolArgumentError::Missing("b".to_owned()));
+    assert_eq!(ttc.get_i64("a").unwrap_err(), ToolArgumentError::InvalidType{ field: "a".to_owned(), expected: "an integer".to_owned(), found: "number".to_owned() });
+}
+
+#[derive(Deserialize, Debug)]
+struct MathArgs{
+    a: f64,
+    op: String,
+    b: f64,
+}
+
+#[test]
+fn test_tool_to_call_parse(){
+    build_logger("BACHUETECH", "BT.AI_TOOL2CALL", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+    let ok = ToolToCall::new("do_basic_math".to_owned(), serde_json::from_str("{\"a\": 2, \"op\": \"*\", \"b\": 4.5}").unwrap());
+    let args: MathArgs = ok.parse_arguments().unwrap();
+    assert_eq!(args.a * args.b, 9.0);
+    assert_eq!(args.op, "*");
+
+    let missing = ToolToCall::new("do_basic_math".to_owned(), serde_json::from_str("{\"a\": 2, \"op\": \"*\"}").unwrap());
+    assert_eq!(missing.parse_arguments::<MathArgs>().unwrap_err(), ToolArgumentError::Missing("b".to_owned()));
+
+    let wrong = ToolToCall::new("do_basic_math".to_owned(), serde_json::from_str("{\"a\": \"two\", \"op\": \"*\", \"b\": 4}").unwrap());
+    assert_eq!(wrong.parse_arguments::<MathArgs>().unwrap_err().get_field(), "a");
+}
+
+}
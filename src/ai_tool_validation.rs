@@ -0,0 +1,394 @@
+use std::{collections::HashMap, fmt};
+
+use serde_json::{Map, Value};
+
+use crate::{ai_tool_to_call::ToolToCall, ai_tools::{Tool, ToolParamProperty}, message::Message};
+
+///Problem found in the arguments of a tool call returned by the AI model
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolCallViolation {
+    UnknownTool(String),
+    MissingArgument(String),
+    UnknownArgument(String),
+    InvalidType { argument: String, expected: String, found: String },
+    InvalidEnumValue { argument: String, value: Value, allowed: Vec<Value> },
+    InvalidValue { argument: String, message: String },
+}
+
+impl fmt::Display for ToolCallViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolCallViolation::UnknownTool(name) => write!(f, "Tool '{}' does not exist", name),
+            ToolCallViolation::MissingArgument(arg) => write!(f, "Required argument '{}' is missing", arg),
+            ToolCallViolation::UnknownArgument(arg) => write!(f, "Argument '{}' is not defined for this tool", arg),
+            ToolCallViolation::InvalidType { argument, expected, found } => write!(f, "Argument '{}' must be of type {} but it is {}", argument, expected, found),
+            ToolCallViolation::InvalidEnumValue { argument, value, allowed } => {
+                let allowed: Vec<String> = allowed.iter().map(|a| a.to_string()).collect();
+                write!(f, "Argument '{}' has value {} but it must be one of {}", argument, value, allowed.join(", "))
+            }
+            ToolCallViolation::InvalidValue { argument, message } => write!(f, "Argument '{}' {}", argument, message),
+        }
+    }
+}
+
+///Normalized JSON Schema type name of a declared parameter type. None if the type is not known.
+pub(crate) fn get_schema_type(type_name: &str) -> Option<&'static str> {
+    match type_name.trim().to_lowercase().as_str() {
+        "string" | "str" => Some("string"),
+        "number" | "decimal" | "float" | "double" => Some("number"),
+        "integer" | "int" => Some("integer"),
+        "boolean" | "bool" => Some("boolean"),
+        "array" => Some("array"),
+        "object" => Some("object"),
+        "null" => Some("null"),
+        _ => None,
+    }
+}
+
+pub(crate) fn get_value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+pub(crate) fn is_value_of_type(schema_type: &str, value: &Value) -> bool {
+    match (schema_type, value) {
+        ("number", Value::Number(_)) => true,
+        ("integer", Value::Number(n)) => n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+        _ => schema_type == get_value_type(value),
+    }
+}
+
+fn check_value(path: &str, prop: &ToolParamProperty, value: &Value, violations: &mut Vec<ToolCallViolation>) {
+    if let Some(schema_type) = prop.get_type() {
+        let expected: Vec<&str> = schema_type.get_names().iter().filter_map(|t| get_schema_type(t)).collect();
+        if !expected.is_empty() && !expected.iter().any(|t| is_value_of_type(t, value)) {
+            violations.push(ToolCallViolation::InvalidType {
+                argument: path.to_owned(),
+                expected: expected.join(" or "),
+                found: get_value_type(value).to_owned(),
+            });
+            return;
+        }
+    }
+
+    if let Some(allowed) = prop.get_enum() && !allowed.contains(value) {
+        violations.push(ToolCallViolation::InvalidEnumValue {
+            argument: path.to_owned(),
+            value: value.clone(),
+            allowed: allowed.clone(),
+        });
+    }
+
+    let mut invalid = |message: String| violations.push(ToolCallViolation::InvalidValue { argument: path.to_owned(), message });
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = prop.get_minimum() && n < min {
+                invalid(format!("must be greater than or equal to {}", min));
+            }
+            if let Some(max) = prop.get_maximum() && n > max {
+                invalid(format!("must be less than or equal to {}", max));
+            }
+            if let Some(min) = prop.get_exclusive_minimum() && n <= min {
+                invalid(format!("must be greater than {}", min));
+            }
+            if let Some(max) = prop.get_exclusive_maximum() && n >= max {
+                invalid(format!("must be less than {}", max));
+            }
+        }
+        Value::String(st) => {
+            let len = st.chars().count() as u64;
+            if let Some(min) = prop.get_min_length() && len < min {
+                invalid(format!("must have at least {} characters", min));
+            }
+            if let Some(max) = prop.get_max_length() && len > max {
+                invalid(format!("must have at most {} characters", max));
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = prop.get_min_items() && len < min {
+                invalid(format!("must have at least {} items", min));
+            }
+            if let Some(max) = prop.get_max_items() && len > max {
+                invalid(format!("must have at most {} items", max));
+            }
+            if let Some(item_prop) = prop.get_items() {
+                for (i, item) in items.iter().enumerate() {
+                    check_value(&format!("{}[{}]", path, i), item_prop, item, violations);
+                }
+            }
+        }
+        Value::Object(obj) => {
+            let empty = Vec::new();
+            if let Some(properties) = prop.get_properties() {
+                //JSON Schema allows undeclared keys unless additionalProperties is false
+                let closed = is_closed(prop.get_extra());
+                check_object(path, properties, prop.get_required().unwrap_or(&empty), closed, obj.iter(), violations);
+            }
+        }
+        _ => {}
+    }
+}
+
+///True if a schema object rejects undeclared keys (`additionalProperties: false`)
+fn is_closed(extra: &Map<String, Value>) -> bool {
+    extra.get("additionalProperties") == Some(&Value::Bool(false))
+}
+
+fn check_object<'a>(path: &str, properties: &HashMap<String, ToolParamProperty>, required: &[String], closed: bool,
+                    args: impl Iterator<Item = (&'a String, &'a Value)>, violations: &mut Vec<ToolCallViolation>) {
+    let get_path = |name: &str| if path.is_empty() { name.to_owned() } else { format!("{}.{}", path, name) };
+    let mut args: Vec<(&String, &Value)> = args.collect();
+    args.sort_by(|a, b| a.0.cmp(b.0));
+
+    for req in required {
+        if !args.iter().any(|(name, _)| *name == req) {
+            violations.push(ToolCallViolation::MissingArgument(get_path(req)));
+        }
+    }
+
+    for (name, value) in args {
+        match properties.get(name) {
+            Some(prop) => check_value(&get_path(name), prop, value, violations),
+            None if closed => violations.push(ToolCallViolation::UnknownArgument(get_path(name))),
+            None => {}
+        }
+    }
+}
+
+///Validate the arguments of `tool_call` against the parameters declared in `tool`. Empty if valid.
+///Nested arguments are reported with their path (e.g. `items[0].name`).
+///Undeclared arguments of the tool are always reported (unless the parameters declare `additionalProperties: true`);
+///undeclared keys of nested objects only when the object declares `additionalProperties: false`.
+pub fn validate_tool_call(tool: &Tool, tool_call: &ToolToCall) -> Vec<ToolCallViolation> {
+    let params = tool.get_parameters();
+    let closed = params.get_extra().get("additionalProperties") != Some(&Value::Bool(true));
+    let mut violations = Vec::new();
+    check_object("", params.get_properties(), params.get_required(), closed, tool_call.get_raw_arguments().iter(), &mut violations);
+    violations
+}
+
+///Problem found in a tool definition, with the tool name and the JSON path of the offending element
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolDefinitionProblem {
+    pub tool: String,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ToolDefinitionProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tool '{}' at {}: {}", self.tool, self.path, self.message)
+    }
+}
+
+const SCHEMA_TYPES: [&str; 7] = ["string", "number", "integer", "boolean", "array", "object", "null"];
+
+struct DefinitionChecker<'a> {
+    tool: &'a str,
+    problems: Vec<ToolDefinitionProblem>,
+}
+
+impl DefinitionChecker<'_> {
+    fn add(&mut self, path: &str, message: String) {
+        self.problems.push(ToolDefinitionProblem { tool: self.tool.to_owned(), path: path.to_owned(), message });
+    }
+
+    fn check_required(&mut self, path: &str, properties: Option<&HashMap<String, ToolParamProperty>>, required: &[String]) {
+        for (i, req) in required.iter().enumerate() {
+            if !properties.is_some_and(|p| p.contains_key(req)) {
+                self.add(&format!("{}.required[{}]", path, i), format!("Required parameter '{}' is not defined in properties", req));
+            }
+        }
+    }
+
+    fn check_property(&mut self, path: &str, prop: &ToolParamProperty, top_level: bool) {
+        if let Some(schema_type) = prop.get_type() {
+            for t in schema_type.get_names() {
+                if !SCHEMA_TYPES.contains(&t.as_str()) {
+                    let hint = match get_schema_type(t) {
+                        Some(valid) => format!(". Use '{}' instead", valid),
+                        None => String::new(),
+                    };
+                    self.add(&format!("{}.type", path), format!("Invalid JSON Schema type '{}'{}", t, hint));
+                }
+            }
+        }
+
+        match prop.get_description() {
+            Some(d) if d.trim().is_empty() => self.add(&format!("{}.description", path), "Empty description".to_owned()),
+            None if top_level => self.add(path, "Missing description".to_owned()),
+            _ => {}
+        }
+
+        if let Some(items) = prop.get_items() {
+            self.check_property(&format!("{}.items", path), items, false);
+        }
+        if let Some(properties) = prop.get_properties() {
+            let mut names: Vec<&String> = properties.keys().collect();
+            names.sort();
+            for n in names {
+                self.check_property(&format!("{}.properties.{}", path, n), &properties[n], false);
+            }
+        }
+        if let Some(required) = prop.get_required() {
+            self.check_required(path, prop.get_properties(), required);
+        }
+    }
+}
+
+///Check tool definitions: JSON Schema type names, `required` entries defined in `properties`, duplicated function names and empty descriptions.
+///Paths are relative to the tools definition document (e.g. `tools[1].function.parameters.properties.a.type`).
+pub fn validate_tool_definitions(tools: &[Tool]) -> Vec<ToolDefinitionProblem> {
+    let mut problems = Vec::new();
+    let mut seen: HashMap<&String, usize> = HashMap::new();
+
+    for (i, tool) in tools.iter().enumerate() {
+        let name = tool.get_function_name();
+        let path = format!("tools[{}]", i);
+        let mut checker = DefinitionChecker { tool: name, problems: Vec::new() };
+
+        if tool.get_type() != "function" {
+            checker.add(&format!("{}.type", path), format!("Invalid tool type '{}'. Only 'function' is supported", tool.get_type()));
+        }
+        if name.trim().is_empty() {
+            checker.add(&format!("{}.function.name", path), "Empty function name".to_owned());
+        }
+        if let Some(first) = seen.insert(name, i) {
+            checker.add(&format!("{}.function.name", path), format!("Duplicated function name. Already defined at tools[{}]", first));
+        }
+        if tool.get_description().trim().is_empty() {
+            checker.add(&format!("{}.function.description", path), "Empty description".to_owned());
+        }
+
+        let params = tool.get_parameters();
+        let params_path = format!("{}.function.parameters", path);
+        if params.get_type() != "object" {
+            checker.add(&format!("{}.type", params_path), format!("Parameters type must be 'object' but it is '{}'", params.get_type()));
+        }
+        let mut names: Vec<&String> = params.get_properties().keys().collect();
+        names.sort();
+        for n in names {
+            checker.check_property(&format!("{}.properties.{}", params_path, n), &params.get_properties()[n], true);
+        }
+        checker.check_required(&params_path, Some(params.get_properties()), params.get_required());
+
+        problems.extend(checker.problems);
+    }
+    problems
+}
+
+///TOOL message telling the AI model why its tool call was rejected
+pub fn get_violations_message(tool_call: &ToolToCall, violations: &[ToolCallViolation]) -> Message {
+    let details: Vec<String> = violations.iter().map(|v| format!("- {}", v)).collect();
+    Message::new_tool_result(
+        format!("Error: invalid call to tool '{}'. Fix the following and try again:\n{}", tool_call.get_function_name(), details.join("\n")),
+        tool_call.get_id().cloned(),
+    )
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_ai_tool_validation {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use crate::{ai_tool_to_call::ToolToCall, ai_tools::Tool, message::MessageRole};
+
+    use super::{get_violations_message, is_value_of_type, validate_tool_call, validate_tool_definitions, ToolCallViolation};
+
+    #[test]
+    fn test_value_types() {
+        assert!(is_value_of_type("number", &Value::from(2)));
+        assert!(is_value_of_type("integer", &Value::from(2.0)));
+        assert!(!is_value_of_type("integer", &Value::from(2.5)));
+        assert!(!is_value_of_type("string", &Value::from(2)));
+    }
+
+    #[test]
+    fn test_violations_message() {
+        let ttc = ToolToCall::new_with_id("call_1".to_owned(), "do_basic_math".to_owned(), HashMap::new());
+        let msg = get_violations_message(&ttc, &[ToolCallViolation::MissingArgument("a".to_owned())]);
+        assert_eq!(msg.get_role().clone(), MessageRole::TOOL);
+        assert_eq!(msg.get_tool_call_id().unwrap(), "call_1");
+        assert_eq!(msg.get_content(), "Error: invalid call to tool 'do_basic_math'. Fix the following and try again:\n- Required argument 'a' is missing");
+    }
+
+    #[test]
+    fn test_validate_nested() {
+        let tool: Tool = serde_json::from_str(r#"{"type": "function", "function": {"name": "add_items", "description": "Add items to an order",
+            "parameters": {"type": "object", "required": ["items"], "properties": {
+                "items": {"type": "array", "minItems": 1, "items": {"type": "object", "required": ["sku", "qty"], "properties": {
+                    "sku": {"type": "string", "minLength": 3},
+                    "qty": {"type": "integer", "minimum": 1, "maximum": 10}}}},
+                "note": {"type": ["string", "null"]}}}}}"#).unwrap();
+
+        let ok: HashMap<String, Value> = serde_json::from_str(r#"{"items": [{"sku": "A-1", "qty": 2}], "note": null}"#).unwrap();
+        assert!(validate_tool_call(&tool, &ToolToCall::new("add_items".to_owned(), ok)).is_empty());
+
+        let bad: HashMap<String, Value> = serde_json::from_str(r#"{"items": [{"sku": "A", "qty": 20}, {"qty": 1.5}], "note": 3}"#).unwrap();
+        let v = validate_tool_call(&tool, &ToolToCall::new("add_items".to_owned(), bad));
+        assert_eq!(v, vec![
+            ToolCallViolation::InvalidValue { argument: "items[0].qty".to_owned(), message: "must be less than or equal to 10".to_owned() },
+            ToolCallViolation::InvalidValue { argument: "items[0].sku".to_owned(), message: "must have at least 3 characters".to_owned() },
+            ToolCallViolation::MissingArgument("items[1].sku".to_owned()),
+            ToolCallViolation::InvalidType { argument: "items[1].qty".to_owned(), expected: "integer".to_owned(), found: "number".to_owned() },
+            ToolCallViolation::InvalidType { argument: "note".to_owned(), expected: "string or null".to_owned(), found: "integer".to_owned() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_additional_properties() {
+        let tool: Tool = serde_json::from_str(r#"{"type": "function", "function": {"name": "set_metadata", "description": "Set order metadata",
+            "parameters": {"type": "object", "properties": {
+                "tags": {"type": "object", "properties": {"source": {"type": "string"}}},
+                "address": {"type": "object", "additionalProperties": false, "properties": {"city": {"type": "string"}}}}}}}"#).unwrap();
+
+        //Open object (additionalProperties defaults to true): undeclared keys are accepted, declared ones still checked
+        let open: HashMap<String, Value> = serde_json::from_str(r#"{"tags": {"source": "web", "campaign": "spring"}}"#).unwrap();
+        assert!(validate_tool_call(&tool, &ToolToCall::new("set_metadata".to_owned(), open)).is_empty());
+        let open: HashMap<String, Value> = serde_json::from_str(r#"{"tags": {"source": 1, "campaign": "spring"}}"#).unwrap();
+        assert_eq!(validate_tool_call(&tool, &ToolToCall::new("set_metadata".to_owned(), open)).len(), 1);
+
+        let closed: HashMap<String, Value> = serde_json::from_str(r#"{"address": {"city": "Paris", "zip": "75001"}, "other": 1}"#).unwrap();
+        assert_eq!(validate_tool_call(&tool, &ToolToCall::new("set_metadata".to_owned(), closed)), vec![
+            ToolCallViolation::UnknownArgument("address.zip".to_owned()),
+            ToolCallViolation::UnknownArgument("other".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn test_validate_definitions() {
+        let tools: Vec<Tool> = serde_json::from_str(r#"[
+            {"type": "function", "function": {"name": "do_basic_math", "description": "Do basic math operations",
+                "parameters": {"type": "object", "required": ["a", "b", "c"], "properties": {
+                    "a": {"type": "decimal", "description": "The first numeric operand"},
+                    "b": {"type": "String", "description": ""}}}}},
+            {"type": "function", "function": {"name": "do_basic_math", "description": " ",
+                "parameters": {"type": "object", "properties": {
+                    "list": {"type": "array", "description": "A list", "items": {"type": "object", "required": ["x"], "properties": {"y": {"type": "float"}}}}}}}}
+        ]"#).unwrap();
+        let problems: Vec<String> = validate_tool_definitions(&tools).iter().map(|p| p.to_string()).collect();
+        assert_eq!(problems, vec![
+            "Tool 'do_basic_math' at tools[0].function.parameters.properties.a.type: Invalid JSON Schema type 'decimal'. Use 'number' instead",
+            "Tool 'do_basic_math' at tools[0].function.parameters.properties.b.type: Invalid JSON Schema type 'String'. Use 'string' instead",
+            "Tool 'do_basic_math' at tools[0].function.parameters.properties.b.description: Empty description",
+            "Tool 'do_basic_math' at tools[0].function.parameters.required[2]: Required parameter 'c' is not defined in properties",
+            "Tool 'do_basic_math' at tools[1].function.name: Duplicated function name. Already defined at tools[0]",
+            "Tool 'do_basic_math' at tools[1].function.description: Empty description",
+            "Tool 'do_basic_math' at tools[1].function.parameters.properties.list.items.properties.y.type: Invalid JSON Schema type 'float'. Use 'number' instead",
+            "Tool 'do_basic_math' at tools[1].function.parameters.properties.list.items.required[0]: Required parameter 'x' is not defined in properties",
+        ]);
+    }
+}
@@ -1,20 +1,85 @@
-use std::{collections::{HashMap, HashSet}, error::Error};
+use std::{collections::{HashMap, HashSet}, error::Error, fmt, fs, path::Path};
 
 use bt_file_utils::get_file;
-use bt_logger::log_warning;
+use bt_logger::{get_error, log_warning};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
 
-use crate::ai_config::{AIConfig, SupportedFunctions};
+use crate::{ai_config::{AIConfig, SupportedFunctions}, ai_tool_schema::AiTool, ai_tool_to_call::ToolToCall, ai_tool_validation::{validate_tool_call, validate_tool_definitions, ToolCallViolation, ToolDefinitionProblem}};
 
 const TOOLS_JSON_DEF: &str = "defs/tools-def.json";
-const TOOLS_JSON_DEF_ENV_VAR_NAME: &str = "BT_AITOOLS_DEFJSONFILE";
+///Environment variable with the path of the JSON tools definition file (default `defs/tools-def.json`)
+pub const TOOLS_JSON_DEF_ENV_VAR_NAME: &str = "BT_AITOOLS_DEFJSONFILE";
 
 #[derive(Debug)]
 pub struct AIToolManager{
     tools: Option<Tools>,
+    definition_problems: Vec<ToolDefinitionProblem>,
+    load_error: Option<ToolRegistrationError>,
     ai_config: AIConfig,
 }
 
+///Result of reading the JSON tools definition file. Without strict loading, a file that cannot be read or parsed
+///gives no tools and the `error`.
+#[derive(Debug, Default)]
+struct LoadedTools {
+    tools: Option<Tools>,
+    problems: Vec<ToolDefinitionProblem>,
+    error: Option<ToolRegistrationError>,
+}
+
+///Error registering or loading tool definitions at runtime
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolRegistrationError {
+    ///A tool with the same function name is already registered
+    DuplicateTool(String),
+    ///A definition file or directory could not be read
+    Io{ path: String, message: String },
+    ///A definition file is not a valid JSON tools definition
+    Parse{ path: String, message: String },
+}
+
+impl fmt::Display for ToolRegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolRegistrationError::DuplicateTool(name) => write!(f, "Tool '{}' is already registered", name),
+            ToolRegistrationError::Io{ path, message } => write!(f, "Error reading tools definition {}. Error: {}", path, message),
+            ToolRegistrationError::Parse{ path, message } => write!(f, "Error parsing tools definition {}. Error: {}", path, message),
+        }
+    }
+}
+
+impl Error for ToolRegistrationError {}
+
+///Reason why no tool definitions can be sent to an AI model
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolSelectionError {
+    ///The platform is not defined in the AI configuration
+    UnknownPlatform(String),
+    ///Neither the model nor a `default` model are defined for the platform
+    UnknownModel{ platform: String, model_id: String },
+    ///The model is configured with `tools: NONE`
+    NoToolsAllowed,
+    ///The JSON tools definition file was not loaded and no tools were registered
+    ToolsNotLoaded,
+    ///Function names configured for the model without a tool definition
+    UndefinedTools(Vec<String>),
+}
+
+impl fmt::Display for ToolSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolSelectionError::UnknownPlatform(p) => write!(f, "Platform {} is not defined", p),
+            ToolSelectionError::UnknownModel{ platform, model_id } => write!(f, "Model {} (or a default model) is not defined for platform {}", model_id, platform),
+            ToolSelectionError::NoToolsAllowed => write!(f, "The model does not allow tools"),
+            ToolSelectionError::ToolsNotLoaded => write!(f, "No tool definitions are loaded"),
+            ToolSelectionError::UndefinedTools(names) => write!(f, "Tools without definition: {}", names.join(", ")),
+        }
+    }
+}
+
+impl Error for ToolSelectionError {}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Tools{
     tools: Vec<Tool>
@@ -39,72 +104,563 @@ pub struct FunctionParameters{
     #[serde(rename = "type")]
     type_: String,
     properties: HashMap<String,ToolParamProperty>,
+    #[serde(default)]
     required: Vec<String>,
+    ///Any other JSON Schema keyword (e.g. additionalProperties), kept as is
+    #[serde(flatten)]
+    extra: Map<String,Value>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+///JSON Schema `type`: a single type name or a list of them (e.g. ["string", "null"])
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SchemaType{
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+///JSON Schema of a tool parameter. Supports a practical subset of JSON Schema; unknown keywords are kept in `extra`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct ToolParamProperty{
-    #[serde(rename = "type")]
-    type_ : String,
-    description: String
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    type_ : Option<SchemaType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    enum_: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    //Arrays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    items: Option<Box<ToolParamProperty>>,
+    #[serde(rename = "minItems", default, skip_serializing_if = "Option::is_none")]
+    min_items: Option<u64>,
+    #[serde(rename = "maxItems", default, skip_serializing_if = "Option::is_none")]
+    max_items: Option<u64>,
+    //Objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    properties: Option<HashMap<String,ToolParamProperty>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    required: Option<Vec<String>>,
+    //Numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    minimum: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    maximum: Option<Number>,
+    #[serde(rename = "exclusiveMinimum", default, skip_serializing_if = "Option::is_none")]
+    exclusive_minimum: Option<Number>,
+    #[serde(rename = "exclusiveMaximum", default, skip_serializing_if = "Option::is_none")]
+    exclusive_maximum: Option<Number>,
+    //Strings
+    #[serde(rename = "minLength", default, skip_serializing_if = "Option::is_none")]
+    min_length: Option<u64>,
+    #[serde(rename = "maxLength", default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    ///Any other JSON Schema keyword, kept as is
+    #[serde(flatten)]
+    extra: Map<String,Value>,
+}
+
+impl Tool {
+    ///Function tool definition
+    pub fn new(function_name: &str, description: &str, parameters: FunctionParameters) -> Self {
+        Self {
+            type_: "function".to_owned(),
+            function: Function {
+                name: function_name.to_owned(),
+                description: description.to_owned(),
+                parameters,
+            },
+        }
+    }
+
+    pub fn get_type(&self) -> &String {
+        &self.type_
+    }
+
+    pub fn get_function_name(&self) -> &String {
+        &self.function.name
+    }
+
+    pub fn get_description(&self) -> &String {
+        &self.function.description
+    }
+
+    pub fn get_parameters(&self) -> &FunctionParameters {
+        &self.function.parameters
+    }
+}
+
+impl FunctionParameters {
+    pub fn new(properties: HashMap<String,ToolParamProperty>, required: Vec<String>) -> Self {
+        Self {
+            type_: "object".to_owned(),
+            properties,
+            required,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn get_type(&self) -> &String {
+        &self.type_
+    }
+
+    pub fn get_properties(&self) -> &HashMap<String,ToolParamProperty> {
+        &self.properties
+    }
+
+    pub fn get_required(&self) -> &Vec<String> {
+        &self.required
+    }
+
+    pub fn get_extra(&self) -> &Map<String,Value> {
+        &self.extra
+    }
+}
+
+impl SchemaType {
+    pub fn get_names(&self) -> Vec<&String> {
+        match self {
+            SchemaType::Single(t) => vec![t],
+            SchemaType::Multiple(ts) => ts.iter().collect(),
+        }
+    }
+}
+
+impl ToolParamProperty {
+    ///Parameter of a JSON Schema type (string, number, integer, boolean, array, object)
+    pub fn new(type_name: &str) -> Self {
+        Self {
+            type_: Some(SchemaType::Single(type_name.to_owned())),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.description = Some(description.to_owned());
+    }
+
+    pub fn set_enum(&mut self, values: Vec<Value>) {
+        self.enum_ = Some(values);
+    }
+
+    pub fn set_items(&mut self, items: ToolParamProperty) {
+        self.items = Some(Box::new(items));
+    }
+
+    pub fn set_properties(&mut self, properties: HashMap<String,ToolParamProperty>, required: Vec<String>) {
+        self.properties = Some(properties);
+        self.required = Some(required);
+    }
+
+    pub fn get_type(&self) -> Option<&SchemaType> {
+        self.type_.as_ref()
+    }
+
+    pub fn get_description(&self) -> Option<&String> {
+        self.description.as_ref()
+    }
+
+    pub fn get_enum(&self) -> Option<&Vec<Value>> {
+        self.enum_.as_ref()
+    }
+
+    pub fn get_default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
+
+    pub fn get_format(&self) -> Option<&String> {
+        self.format.as_ref()
+    }
+
+    pub fn get_items(&self) -> Option<&ToolParamProperty> {
+        self.items.as_deref()
+    }
+
+    pub fn get_min_items(&self) -> Option<u64> {
+        self.min_items
+    }
+
+    pub fn get_max_items(&self) -> Option<u64> {
+        self.max_items
+    }
+
+    pub fn get_properties(&self) -> Option<&HashMap<String,ToolParamProperty>> {
+        self.properties.as_ref()
+    }
+
+    pub fn get_required(&self) -> Option<&Vec<String>> {
+        self.required.as_ref()
+    }
+
+    pub fn get_minimum(&self) -> Option<f64> {
+        self.minimum.as_ref().and_then(|n| n.as_f64())
+    }
+
+    pub fn get_maximum(&self) -> Option<f64> {
+        self.maximum.as_ref().and_then(|n| n.as_f64())
+    }
+
+    pub fn get_exclusive_minimum(&self) -> Option<f64> {
+        self.exclusive_minimum.as_ref().and_then(|n| n.as_f64())
+    }
+
+    pub fn get_exclusive_maximum(&self) -> Option<f64> {
+        self.exclusive_maximum.as_ref().and_then(|n| n.as_f64())
+    }
+
+    pub fn get_min_length(&self) -> Option<u64> {
+        self.min_length
+    }
+
+    pub fn get_max_length(&self) -> Option<u64> {
+        self.max_length
+    }
+
+    pub fn get_pattern(&self) -> Option<&String> {
+        self.pattern.as_ref()
+    }
+
+    pub fn get_extra(&self) -> &Map<String,Value> {
+        &self.extra
+    }
 }
 
 impl AIToolManager {
     pub fn new(run_environment: &str) -> Result<Self, Box<dyn Error>>  {
-        let tools_def: String;
-        match get_file(TOOLS_JSON_DEF_ENV_VAR_NAME, TOOLS_JSON_DEF){
-            Ok(j_file_conf) => tools_def = j_file_conf,
+        Ok(Self::new_with_config(AIConfig::new(run_environment)?))
+    }
+
+    ///Tool manager that fails if the JSON tools definition file is missing, cannot be parsed or has any problem
+    pub fn new_strict(run_environment: &str) -> Result<Self, Box<dyn Error>>  {
+        Self::new_with_config_strict(AIConfig::new(run_environment)?)
+    }
+
+    ///Tool manager for an already loaded AI configuration. Tools are read from the JSON tools definition file.
+    ///Problems in the file are logged and the manager continues with the tools it could load.
+    pub fn new_with_config(ai_config: AIConfig) -> Self {
+        let loaded = Self::read_tools(false).unwrap_or_default();
+        Self{
+            tools: loaded.tools,
+            definition_problems: loaded.problems,
+            load_error: loaded.error,
+            ai_config,
+        }
+    }
+
+    ///Strict version of `new_with_config`
+    pub fn new_with_config_strict(ai_config: AIConfig) -> Result<Self, Box<dyn Error>> {
+        let loaded = Self::read_tools(true)?;
+        Ok(Self{
+            tools: loaded.tools,
+            definition_problems: loaded.problems,
+            load_error: None,
+            ai_config,
+        })
+    }
+
+    fn read_tools(strict: bool) -> Result<LoadedTools, String> {
+        let path = std::env::var(TOOLS_JSON_DEF_ENV_VAR_NAME).unwrap_or_else(|_| TOOLS_JSON_DEF.to_owned());
+        let tools_def = match get_file(TOOLS_JSON_DEF_ENV_VAR_NAME, TOOLS_JSON_DEF){
+            Ok(j_file_conf) => j_file_conf,
             Err(e) => {
+                if strict {
+                    return Err(get_error!("read_tools","Error loading JSON tools configuration file. Error: {}",e.to_string()));
+                }
                 log_warning!("new","Error loding JSON tools configuration file. Using Empty tools as default. Error: {}",e.to_string()); 
-                return Ok(Self{
-                    tools: None,
-                    ai_config: AIConfig::new(run_environment)?, 
-                }) //tools_def = "".to_owned();
+                return Ok(LoadedTools { error: Some(ToolRegistrationError::Io{ path, message: e.to_string() }), ..Default::default() })
             },
+        };
+
+        Self::read_tools_def(&tools_def, &path, strict)
+    }
+
+    ///Parse and check the content of a JSON tools definition file. `path` is only used in the messages.
+    fn read_tools_def(tools_def: &str, path: &str, strict: bool) -> Result<LoadedTools, String> {
+        let tools = match Self::parse_tools(tools_def, path) {
+            Ok(t) => t,
+            Err(e) => {
+                if strict {
+                    return Err(get_error!("read_tools", "{}", e));
+                }
+                log_warning!("AIToolManager:new", "Error loading tools or No tools available. {}", e) ;
+                return Ok(LoadedTools { error: Some(e), ..Default::default() })
+            }
+        };
+
+        let problems = validate_tool_definitions(&tools.tools);
+        if strict && !problems.is_empty() {
+            let details: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+            return Err(get_error!("read_tools", "{} problems found in JSON tools definition:\n{}", problems.len(), details.join("\n")));
+        }
+        for p in &problems {
+            log_warning!("read_tools", "Tools definition problem: {}", p);
         }
+        Ok(LoadedTools { tools: Some(tools), problems, error: None })
+    }
+
+    fn parse_tools(tools_def: &str, path: &str) -> Result<Tools, ToolRegistrationError> {
+        let mut json_de = serde_json::Deserializer::from_str(tools_def);
+        serde_path_to_error::deserialize(&mut json_de).map_err(|e| ToolRegistrationError::Parse{
+            path: path.to_owned(),
+            message: format!("at {}: {}", e.path(), e.inner()),
+        })
+    }
+
+    ///Why the JSON tools definition file could not be read or parsed when the manager was created, if it could not
+    pub fn get_load_error(&self) -> Option<&ToolRegistrationError> {
+        self.load_error.as_ref()
+    }
 
-        match serde_json::from_str(&tools_def) {
-            Ok(t) => {
-                Ok(Self{ 
-                    tools: Some(t),
-                    ai_config: AIConfig::new(run_environment)?,
-                }) //json_tools: tools_def, tool_count: num_tools}
+    ///Problems found in the tool definitions when they were loaded or registered
+    pub fn get_definition_problems(&self) -> &Vec<ToolDefinitionProblem> {
+        &self.definition_problems
+    }
+
+    pub fn get_ai_config(&self) -> &AIConfig {
+        &self.ai_config
+    }
+
+    ///Tool definition of a function by name
+    pub fn get_tool(&self, function_name: &str) -> Option<&Tool> {
+        self.tools.as_ref().and_then(|t| t.tools.iter().find(|tool| tool.function.name == function_name))
+    }
+
+    ///Registered tool definitions, in registration order
+    pub fn list_tools(&self) -> &[Tool] {
+        self.tools.as_ref().map(|t| t.tools.as_slice()).unwrap_or_default()
+    }
+
+    ///Add a tool definition. Fails if a tool with the same function name is already registered.
+    ///Definition problems are logged and kept in `get_definition_problems`; the tool is still registered.
+    pub fn register_tool(&mut self, tool: Tool) -> Result<(), ToolRegistrationError> {
+        self.register_tools(vec![tool]).map(|_| ())
+    }
+
+    ///Add the tool definition of a type that implements `AiTool`
+    pub fn register_ai_tool<T: AiTool>(&mut self) -> Result<(), ToolRegistrationError> {
+        self.register_tool(T::get_tool())
+    }
+
+    ///Remove a tool definition by function name. Returns the removed tool.
+    pub fn unregister_tool(&mut self, function_name: &str) -> Option<Tool> {
+        let tools = &mut self.tools.as_mut()?.tools;
+        let pos = tools.iter().position(|t| t.function.name == function_name)?;
+        self.definition_problems.retain(|p| p.tool != function_name);
+        Some(tools.remove(pos))
+    }
+
+    ///Add the tools of a JSON tools definition file (same format as `BT_AITOOLS_DEFJSONFILE`).
+    ///Nothing is added if any of its tools is already registered. Returns the number of tools added.
+    pub fn load_tools_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, ToolRegistrationError> {
+        let tools = Self::read_tools_file(path.as_ref())?;
+        self.register_tools(tools)
+    }
+
+    ///Add the tools of every `.json` file in a directory, in file name order.
+    ///Nothing is added if any file fails or any tool is already registered. Returns the number of tools added.
+    pub fn load_tools_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize, ToolRegistrationError> {
+        let dir = dir.as_ref();
+        let io_error = |e: std::io::Error| ToolRegistrationError::Io{ path: dir.display().to_string(), message: e.to_string() };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+                files.push(path);
             }
-            Err(e) => {
-                log_warning!("AIToolManager:new", "Error loading tools or No tools available: {}", e) ;
-                Ok(Self{
-                    tools: None, 
-                    ai_config: AIConfig::new(run_environment)?,
-                }) //json_tools: "".to_owned(), tool_count: 0 }
+        }
+        files.sort();
+
+        let mut tools = Vec::new();
+        for f in &files {
+            tools.extend(Self::read_tools_file(f)?);
+        }
+        self.register_tools(tools)
+    }
+
+    fn read_tools_file(path: &Path) -> Result<Vec<Tool>, ToolRegistrationError> {
+        let tools_def = fs::read_to_string(path).map_err(|e| ToolRegistrationError::Io{ path: path.display().to_string(), message: e.to_string() })?;
+        Ok(Self::parse_tools(&tools_def, &path.display().to_string())?.tools)
+    }
+
+    fn register_tools(&mut self, new_tools: Vec<Tool>) -> Result<usize, ToolRegistrationError> {
+        let mut names: HashSet<&String> = self.list_tools().iter().map(|t| &t.function.name).collect();
+        for t in &new_tools {
+            if !names.insert(&t.function.name) {
+                return Err(ToolRegistrationError::DuplicateTool(t.function.name.clone()));
             }
         }
+
+        let problems = validate_tool_definitions(&new_tools);
+        for p in &problems {
+            log_warning!("register_tools", "Tools definition problem: {}", p);
+        }
+        self.definition_problems.extend(problems);
+
+        let count = new_tools.len();
+        self.tools.get_or_insert_with(|| Tools{ tools: Vec::new() }).tools.extend(new_tools);
+        Ok(count)
+    }
+
+    ///Check the arguments of a tool call returned by the AI model against the tool definition. Empty if valid.
+    pub fn validate_tool_call(&self, tool_call: &ToolToCall) -> Vec<ToolCallViolation> {
+        match self.get_tool(tool_call.get_function_name()) {
+            Some(tool) => validate_tool_call(tool, tool_call),
+            None => vec![ToolCallViolation::UnknownTool(tool_call.get_function_name().clone())],
+        }
     }
 
+    ///Tools the model can use. `None` if the model allows no tools or they are not available.
+    ///Configured function names without a definition are skipped (see `try_get_tools`).
     pub fn get_tools(&self, platform_name: &String, model_id: &String) -> Option<Vec<Tool>> {
-        if let Some(p) = self.ai_config.get_models(platform_name) {
-            if let Some(tool_model) = p.get(model_id) {
-                    self.get_common_tools(tool_model.tools.clone())
-            } else {
-                if model_id.to_lowercase() == "default" { //This is a Stop condition. meaning there is no default defined ^^^
-                    None 
-                } else {
-                    self.get_tools(platform_name, &"default".to_owned())
+        match self.get_model_functions(platform_name, model_id) {
+            Ok(functions) => self.get_common_tools(functions),
+            Err(e) => {
+                log_warning!("get_tools", "No tools for model {} of platform {}. {}", model_id, platform_name, e);
+                None
+            }
+        }
+    }
+
+    ///Tools the model can use, or the reason why there are none.
+    ///Fails with `UndefinedTools` if any configured function name has no definition.
+    pub fn try_get_tools(&self, platform_name: &String, model_id: &String) -> Result<Vec<Tool>, ToolSelectionError> {
+        self.try_get_common_tools(self.get_model_functions(platform_name, model_id)?)
+    }
+
+    fn get_model_functions(&self, platform_name: &String, model_id: &String) -> Result<SupportedFunctions, ToolSelectionError> {
+        let models = self.ai_config.get_models(platform_name).ok_or_else(|| ToolSelectionError::UnknownPlatform(platform_name.clone()))?;
+        match models.get(model_id).or_else(|| models.get("default")) {
+            Some(tool_model) => Ok(tool_model.tools.clone()),
+            None => Err(ToolSelectionError::UnknownModel{ platform: platform_name.clone(), model_id: model_id.clone() }),
+        }
+    }
+
+    ///Tools matching the supported functions. `None` if no tools are allowed or loaded.
+    ///Function names without a definition are skipped with a warning.
+    pub fn get_common_tools(&self, functions: SupportedFunctions) -> Option<Vec<Tool>> {
+        match self.select_tools(functions) {
+            Ok((tools, undefined)) => {
+                if !undefined.is_empty() {
+                    log_warning!("get_common_tools", "{}", ToolSelectionError::UndefinedTools(undefined));
                 }
+                Some(tools)
             }
+            Err(e) => {
+                if e != ToolSelectionError::NoToolsAllowed {
+                    log_warning!("get_common_tools", "{}", e);
+                }
+                None
+            }
+        }
+    }
+
+    ///Tools matching the supported functions, or the reason why they cannot be provided
+    pub fn try_get_common_tools(&self, functions: SupportedFunctions) -> Result<Vec<Tool>, ToolSelectionError> {
+        let (tools, undefined) = self.select_tools(functions)?;
+        if undefined.is_empty() {
+            Ok(tools)
         } else {
-            None
+            Err(ToolSelectionError::UndefinedTools(undefined))
+        }
+    }
+
+    ///Defined tools, in definition order, and the requested names or groups without definition
+    fn select_tools(&self, functions: SupportedFunctions) -> Result<(Vec<Tool>, Vec<String>), ToolSelectionError> {
+        if functions == SupportedFunctions::NONE {
+            return Err(ToolSelectionError::NoToolsAllowed);
+        }
+        let Some(tools) = &self.tools else {
+            return Err(ToolSelectionError::ToolsNotLoaded);
+        };
+        let mut undefined = Vec::new();
+        let selected = self.resolve_functions(&functions, &mut undefined);
+        Ok((tools.tools.iter().filter(|t| selected.contains(&t.function.name)).cloned().collect(), undefined))
+    }
+
+    ///Function names selected by the supported functions
+    fn resolve_functions(&self, functions: &SupportedFunctions, undefined: &mut Vec<String>) -> HashSet<String> {
+        let mut selected = HashSet::new();
+        match functions {
+            SupportedFunctions::NONE => {},
+            SupportedFunctions::ALL => selected.extend(self.list_tools().iter().map(|t| t.function.name.clone())),
+            SupportedFunctions::Functions(entries) => {
+                for e in entries {
+                    self.resolve_entry(e, true, &mut selected, undefined, &mut Vec::new());
+                }
+            },
+            SupportedFunctions::Except(base, exclude) => {
+                selected = self.resolve_functions(base, undefined);
+                let mut excluded = HashSet::new();
+                for e in exclude {
+                    //Excluding a function that is not defined is not a problem
+                    self.resolve_entry(e, false, &mut excluded, undefined, &mut Vec::new());
+                }
+                selected.retain(|n| !excluded.contains(n));
+            },
+        }
+        selected
+    }
+
+    fn resolve_entry(&self, entry: &str, report_names: bool, selected: &mut HashSet<String>, undefined: &mut Vec<String>, visiting: &mut Vec<String>) {
+        let entry = entry.trim();
+        if let Some(group) = entry.strip_prefix('@') {
+            if visiting.iter().any(|g| g == group) {
+                log_warning!("resolve_entry", "Tool group {} references itself. Reference ignored", group);
+                return;
+            }
+            match self.ai_config.get_tool_group(group) {
+                Some(group_entries) => {
+                    visiting.push(group.to_owned());
+                    for e in group_entries {
+                        self.resolve_entry(e, report_names, selected, undefined, visiting);
+                    }
+                    visiting.pop();
+                },
+                None => Self::add_undefined(undefined, entry),
+            }
+        } else if entry.contains(['*', '?']) {
+            selected.extend(self.list_tools().iter().filter(|t| matches_pattern(entry, &t.function.name)).map(|t| t.function.name.clone()));
+        } else if self.get_tool(entry).is_some() {
+            selected.insert(entry.to_owned());
+        } else if report_names && !entry.is_empty() {
+            Self::add_undefined(undefined, entry);
+        }
+    }
+
+    fn add_undefined(undefined: &mut Vec<String>, entry: &str) {
+        if !undefined.iter().any(|u| u == entry) {
+            undefined.push(entry.to_owned());
         }
     }
+}
 
-    pub fn get_common_tools(&self, functions: SupportedFunctions) ->Option<Vec<Tool>>{
-        match functions{
-            SupportedFunctions::NONE => None,
-            SupportedFunctions::ALL =>  Some(self.tools.clone().unwrap().tools),
-            SupportedFunctions::Functions(func) => {
-                let set2: HashSet<String> = func.into_iter().collect();
-                Some(self.tools.clone().unwrap().tools.into_iter().filter(|item| set2.contains(&item.function.name)).collect()) } ,
+///Glob match of a function name: `*` is any sequence of characters and `?` any single character
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ni));
+            pi += 1;
+        } else if let Some((bp, bn)) = backtrack {
+            pi = bp + 1;
+            ni = bn + 1;
+            backtrack = Some((bp, bn + 1));
+        } else {
+            return false;
         }
     }
+    p[pi..].iter().all(|c| *c == '*')
 }
 
 
@@ -115,8 +671,15 @@ impl AIToolManager {
 #[cfg(test)]
 mod tests_ai_tools{
     use bt_logger::{build_logger, LogLevel, LogTarget};
-    use crate::ai_config::SupportedFunctions;
-    use super::AIToolManager;
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use crate::{ai_config::SupportedFunctions, ai_tool_to_call::ToolToCall, ai_tool_validation::ToolCallViolation};
+    use crate::ai_config::AIConfig;
+    use crate::ai_config_validation::validate_config;
+    use yaml_rust2::YamlLoader;
+    use super::{matches_pattern, AIToolManager, FunctionParameters, SchemaType, Tool, ToolRegistrationError, ToolSelectionError};
 
     #[test]
     fn test_ai_get_tools_ok(){
@@ -208,6 +771,196 @@ mod tests_ai_tools{
         assert_eq!(aitm.get_common_tools(sf).unwrap().len(),0); //Zero function in common
     }
 
+    #[test]
+    fn test_ai_toolmgr_validate_call(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let aitm = AIToolManager::new("dev").unwrap();
+        let args: HashMap<String, Value> = serde_json::from_str("{\"a\": 2, \"op\": \"+\", \"b\": 3.5}").unwrap();
+        assert!(aitm.validate_tool_call(&ToolToCall::new("do_basic_math".to_owned(), args)).is_empty());
+
+        let args: HashMap<String, Value> = serde_json::from_str("{\"a\": \"2\", \"op\": \"%\", \"c\": 1}").unwrap();
+        let v = aitm.validate_tool_call(&ToolToCall::new("do_basic_math".to_owned(), args));
+        assert_eq!(v.len(), 4);
+        assert!(v.contains(&ToolCallViolation::MissingArgument("b".to_owned())));
+        assert!(v.contains(&ToolCallViolation::UnknownArgument("c".to_owned())));
+        assert!(v.contains(&ToolCallViolation::InvalidType{ argument: "a".to_owned(), expected: "number".to_owned(), found: "string".to_owned() }));
+        assert!(v.contains(&ToolCallViolation::InvalidEnumValue{ argument: "op".to_owned(), value: Value::from("%"), allowed: ["+", "-", "*", "/", "^"].iter().map(|o| Value::from(*o)).collect() }));
+
+        let v = aitm.validate_tool_call(&ToolToCall::new("do_nothing".to_owned(), HashMap::new()));
+        assert_eq!(v, vec![ToolCallViolation::UnknownTool("do_nothing".to_owned())]);
+    }
+
+    #[test]
+    fn test_tool_schema_roundtrip(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let json_def = r#"{"type": "function", "function": {"name": "add_items", "description": "Add items to an order",
+            "parameters": {"type": "object", "additionalProperties": false, "required": ["items"], "properties": {
+                "items": {"type": "array", "description": "Items to add", "minItems": 1, "maxItems": 20,
+                    "items": {"type": "object", "required": ["sku"], "properties": {
+                        "sku": {"type": "string", "pattern": "^[A-Z]-[0-9]+$", "minLength": 3, "maxLength": 12},
+                        "qty": {"type": "integer", "minimum": 1, "maximum": 10, "default": 1},
+                        "price": {"type": "number", "exclusiveMinimum": 0.0}}}},
+                "op": {"type": "string", "enum": ["+", "-"], "default": "+"},
+                "when": {"type": ["string", "null"], "format": "date-time", "examples": ["2025-03-27T18:45:00Z"]}}}}}"#;
+        let tool: Tool = serde_json::from_str(json_def).unwrap();
+        let expected: Value = serde_json::from_str(json_def).unwrap();
+        assert_eq!(serde_json::to_value(&tool).unwrap(), expected);
+
+        let props = tool.get_parameters().get_properties();
+        let items = props["items"].get_items().unwrap();
+        assert_eq!(items.get_properties().unwrap()["qty"].get_maximum(), Some(10.0));
+        assert_eq!(props["when"].get_type().unwrap(), &SchemaType::Multiple(vec!["string".to_owned(), "null".to_owned()]));
+        assert_eq!(props["when"].get_extra()["examples"][0], "2025-03-27T18:45:00Z");
+        assert_eq!(tool.get_parameters().get_extra()["additionalProperties"], false);
+    }
+
+    #[test]
+    fn test_ai_toolmgr_strict(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let aitm = AIToolManager::new_strict("dev").unwrap();
+        assert!(aitm.get_definition_problems().is_empty());
+        assert_eq!(aitm.tools.unwrap().tools.len(),3);
+    }
+
+    #[test]
+    fn test_ai_toolmgr_strict_errors(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let tool = |name: &str| format!("{{\"type\": \"function\", \"function\": {{\"name\": \"{}\", \"description\": \"A tool\", \"parameters\": {{\"type\": \"object\", \"properties\": {{}}}}}}}}", name);
+
+        let malformed = "{\"tools\": [{\"type\": \"function\", \"function\": {\"description\": \"No name\"}}]}";
+        let err = AIToolManager::read_tools_def(malformed, "bad.json", true).unwrap_err();
+        assert!(err.contains("bad.json") && err.contains("tools[0].function"), "{}", err);
+        //Not strict: no tools and the parse error is kept
+        let loaded = AIToolManager::read_tools_def(malformed, "bad.json", false).unwrap();
+        assert!(loaded.tools.is_none());
+        assert!(matches!(loaded.error, Some(ToolRegistrationError::Parse{ path, .. }) if path == "bad.json"));
+
+        let duplicated = format!("{{\"tools\": [{}, {}]}}", tool("get_time"), tool("get_time"));
+        let err = AIToolManager::read_tools_def(&duplicated, "dup.json", true).unwrap_err();
+        assert!(err.contains("1 problems found in JSON tools definition"), "{}", err);
+        assert!(err.contains("Tool 'get_time' at tools[1].function.name: Duplicated function name. Already defined at tools[0]"), "{}", err);
+        let loaded = AIToolManager::read_tools_def(&duplicated, "dup.json", false).unwrap();
+        assert_eq!((loaded.tools.unwrap().tools.len(), loaded.problems.len()), (2, 1));
+        assert!(loaded.error.is_none());
+    }
+
+    #[test]
+    fn test_ai_toolmgr_load_error_validation(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let yml = YamlLoader::load_from_str("
+prod:
+  platform:
+    - name: LOCAL
+      models:
+        - model_id: default
+          tools: NONE
+").unwrap();
+        //No model uses tools: a tools definition that cannot be parsed is still an error
+        let error = AIToolManager::read_tools_def("{\"tools\": [", "bad.json", false).unwrap().error;
+        let aitm = AIToolManager{ tools: None, definition_problems: Vec::new(), load_error: error, ai_config: AIConfig::new_from_yaml(&yml[0], "prod") };
+        let v = validate_config(&aitm, "prod");
+        assert!(!v.is_valid());
+        assert_eq!(v.get_errors()[0].path, "bad.json");
+        assert!(v.get_errors()[0].message.starts_with("Error parsing tools definition bad.json"), "{}", v.get_errors()[0].message);
+    }
+
+    #[test]
+    fn test_ai_toolmgr_register(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let mut aitm = AIToolManager::new("dev").unwrap();
+        let tool = Tool::new("get_time", "Current time of a city", FunctionParameters::new(HashMap::new(), Vec::new()));
+        aitm.register_tool(tool.clone()).unwrap();
+        assert_eq!(aitm.list_tools().len(), 4);
+        assert!(aitm.get_tool("get_time").is_some());
+        assert_eq!(aitm.register_tool(tool), Err(ToolRegistrationError::DuplicateTool("get_time".to_owned())));
+
+        assert_eq!(aitm.unregister_tool("get_time").unwrap().get_function_name(), "get_time");
+        assert!(aitm.unregister_tool("get_time").is_none());
+        assert_eq!(aitm.list_tools().len(), 3);
+    }
+
+    #[test]
+    fn test_ai_toolmgr_load_dir(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let dir = std::env::temp_dir().join(format!("bt_ai_tools_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let def = |name: &str| format!("{{\"tools\": [{{\"type\": \"function\", \"function\": {{\"name\": \"{}\", \"description\": \"Plugin tool\", \"parameters\": {{\"type\": \"object\", \"properties\": {{}}}}}}}}]}}", name);
+        std::fs::write(dir.join("a.json"), def("plugin_a")).unwrap();
+        std::fs::write(dir.join("b.json"), def("plugin_b")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a tool").unwrap();
+
+        let mut aitm = AIToolManager::new("dev").unwrap();
+        assert_eq!(aitm.load_tools_dir(&dir).unwrap(), 2);
+        assert_eq!(aitm.list_tools()[4].get_function_name(), "plugin_b");
+        //Loading again fails and adds nothing
+        assert_eq!(aitm.load_tools_file(dir.join("a.json")), Err(ToolRegistrationError::DuplicateTool("plugin_a".to_owned())));
+        assert_eq!(aitm.load_tools_file("defs/tools-def.json"), Err(ToolRegistrationError::DuplicateTool("get_current_weather".to_owned())));
+        assert_eq!(aitm.list_tools().len(), 5);
+
+        std::fs::write(dir.join("c.json"), "{\"tools\": [{\"type\": \"function\"}]}").unwrap();
+        assert!(matches!(aitm.load_tools_file(dir.join("c.json")), Err(ToolRegistrationError::Parse{..})));
+        assert!(matches!(aitm.load_tools_dir(dir.join("missing")), Err(ToolRegistrationError::Io{..})));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ai_toolmgr_try_get_tools(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let aitm = AIToolManager::new("dev").unwrap();
+        assert_eq!(aitm.try_get_tools(&"OLLAMALOCAL".to_owned(), &"llama3.1".to_owned()).unwrap().len(), 3);
+        assert_eq!(aitm.try_get_tools(&"OLLAMALOCAL".to_owned(), &"guardian".to_owned()).unwrap_err(), ToolSelectionError::NoToolsAllowed);
+        assert_eq!(aitm.try_get_tools(&"INVALID".to_owned(), &"llama3.1".to_owned()).unwrap_err(), ToolSelectionError::UnknownPlatform("INVALID".to_owned()));
+
+        let sf = SupportedFunctions::Functions(vec!["do_basic_math".to_owned(), "do_nothing".to_owned(), "do_less".to_owned()]);
+        assert_eq!(aitm.try_get_common_tools(sf.clone()).unwrap_err(), ToolSelectionError::UndefinedTools(vec!["do_nothing".to_owned(), "do_less".to_owned()]));
+        //The Option API keeps the defined ones
+        assert_eq!(aitm.get_common_tools(sf).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_ai_toolmgr_no_tools_loaded(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let aitm = AIToolManager{ tools: None, definition_problems: Vec::new(), load_error: None, ai_config: AIConfig::new("dev").unwrap() };
+        assert_eq!(aitm.try_get_common_tools(SupportedFunctions::ALL).unwrap_err(), ToolSelectionError::ToolsNotLoaded);
+        assert_eq!(aitm.try_get_tools(&"OLLAMALOCAL".to_owned(), &"llama3.1".to_owned()).unwrap_err(), ToolSelectionError::ToolsNotLoaded);
+        assert!(aitm.get_common_tools(SupportedFunctions::ALL).is_none());
+        assert!(aitm.get_common_tools(SupportedFunctions::Functions(vec!["do_basic_math".to_owned()])).is_none());
+        assert!(aitm.get_tools(&"OLLAMALOCAL".to_owned(), &"llama3.1".to_owned()).is_none());
+    }
 
+    #[test]
+    fn test_matches_pattern(){
+        assert!(matches_pattern("math_*", "math_pow"));
+        assert!(matches_pattern("*_math*", "do_basic_math"));
+        assert!(matches_pattern("get_?ime", "get_time"));
+        assert!(!matches_pattern("math_*", "do_math"));
+        assert!(!matches_pattern("get_?ime", "get_tttime"));
+    }
+
+    #[test]
+    fn test_ai_toolmgr_patterns_groups(){
+        build_logger("BACHUETECH", "BT.AI_CONFIG", LogLevel::VERBOSE, LogTarget::STD_ERROR, None );
+        let yml = YamlLoader::load_from_str("
+dev:
+  tool_groups:
+    math: [do_*math*, '@loop']
+    loop: ['@math', do_basic_math]
+    weather: [get_current_weather]
+").unwrap();
+        let aitm = AIToolManager{ tools: AIToolManager::new("dev").unwrap().tools, definition_problems: Vec::new(), load_error: None, ai_config: AIConfig::new_from_yaml(&yml[0], "dev") };
+        let names = |sf: &str| -> Vec<String> {
+            aitm.try_get_common_tools(SupportedFunctions::from(sf.to_owned())).unwrap().iter().map(|t| t.get_function_name().clone()).collect()
+        };
 
-}
\ No newline at end of file
+        //Definition order, no duplicates
+        assert_eq!(names("do_math_expressions, do_*"), vec!["do_basic_math", "do_math_expressions"]);
+        assert_eq!(names("@math"), vec!["do_basic_math", "do_math_expressions"]);
+        assert_eq!(names("ALL except @weather"), vec!["do_basic_math", "do_math_expressions"]);
+        assert_eq!(names("ALL except do_basic_math, not_defined"), vec!["get_current_weather", "do_math_expressions"]);
+        assert_eq!(names("@math except *_expressions"), vec!["do_basic_math"]);
+        assert!(names("shell_*").is_empty());
+
+        assert_eq!(aitm.try_get_common_tools(SupportedFunctions::from("@web, do_basic_math, rm".to_owned())).unwrap_err(),
+            ToolSelectionError::UndefinedTools(vec!["@web".to_owned(), "rm".to_owned()]));
+    }
+}
@@ -0,0 +1,58 @@
+//! Validate the AI configuration of an environment and its JSON tools definition.
+//!
+//! Usage: `validate_ai_config [--effective] <run_env> [ai-config.yml] [tools-def.json]`
+//!
+//! `--effective` prints the configuration of the environment merged with `common` and its `extends` environments.
+//! Exit code 0 if there are no errors, 1 if errors were found and 2 if the files could not be loaded.
+
+use std::{env, process::ExitCode};
+
+use bt_ai_core::{ai_config::{AIConfig, AI_YML_CONFIG_ENV_VAR_NAME}, ai_tools::TOOLS_JSON_DEF_ENV_VAR_NAME};
+use bt_logger::{build_logger, LogLevel, LogTarget};
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().collect();
+    let effective = args.iter().any(|a| a == "--effective");
+    args.retain(|a| a != "--effective");
+    let Some(run_env) = args.get(1) else {
+        eprintln!("Usage: {} [--effective] <run_env> [ai-config.yml] [tools-def.json]", args[0]);
+        return ExitCode::from(2);
+    };
+    // SAFETY: set before any other thread is started
+    unsafe {
+        if let Some(cfg_file) = args.get(2) {
+            env::set_var(AI_YML_CONFIG_ENV_VAR_NAME, cfg_file);
+        }
+        if let Some(tools_file) = args.get(3) {
+            env::set_var(TOOLS_JSON_DEF_ENV_VAR_NAME, tools_file);
+        }
+    }
+    build_logger("BACHUETECH", "BT.VALIDATE_AI_CONFIG", LogLevel::ERROR, LogTarget::STD_ERROR, None);
+
+    if effective {
+        return match AIConfig::get_effective_config(run_env) {
+            Ok(cfg) => {
+                println!("{}", cfg);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Unable to load the AI configuration: {}", e);
+                ExitCode::from(2)
+            }
+        };
+    }
+
+    let validation = match AIConfig::validate(run_env) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to load the AI configuration: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    for issue in validation.get_issues() {
+        println!("{}", issue);
+    }
+    println!("{} errors, {} warnings", validation.get_errors().len(), validation.get_warnings().len());
+    if validation.is_valid() { ExitCode::SUCCESS } else { ExitCode::from(1) }
+}
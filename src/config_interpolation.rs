@@ -0,0 +1,365 @@
+use std::{env, fmt, fs, sync::Arc};
+
+use yaml_rust2::{yaml::Hash, Yaml};
+
+///Prefix of a string value read from a file (`file:/run/secrets/api_key`). The content is a secret.
+const FILE_REF_PREFIX: &str = "file:";
+///Environment variables whose name contains any of these words hold secrets
+const SECRET_VAR_WORDS: [&str; 5] = ["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD"];
+const REDACTED: &str = "***";
+
+///A secret configuration value (API key, password, ...). `Debug` and `Display` show `***`.
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+///Resolved secret values, to remove them from `Debug` output and log messages
+#[derive(Clone, Default, PartialEq)]
+pub struct SecretValues {
+    values: Arc<Vec<String>>,
+}
+
+impl fmt::Debug for SecretValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretValues({})", self.values.len())
+    }
+}
+
+impl SecretValues {
+    fn new(mut values: Vec<String>) -> Self {
+        values.retain(|v| !v.is_empty());
+        //Longest first so a secret containing another one is fully redacted
+        values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        values.dedup();
+        Self { values: Arc::new(values) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    ///The text with every secret value replaced by `***`
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_owned();
+        for v in self.values.iter() {
+            out = out.replace(v.as_str(), REDACTED);
+        }
+        out
+    }
+}
+
+///`${VAR}` without value or `file:` reference that could not be read. The value is left as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpolationProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for InterpolationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+///YAML configuration with its variables and file references resolved.
+///No `Debug`: the YAML holds the secrets in clear.
+pub struct Interpolated {
+    pub yaml: Yaml,
+    pub secrets: SecretValues,
+    pub problems: Vec<InterpolationProblem>,
+}
+
+///Resolve the string values of a YAML configuration node:
+///- `${VAR}` is replaced by the environment variable VAR and `${VAR:-default}` uses `default` if VAR is unset or empty.
+///  A value that is only a variable gets the YAML type of its content if it is written exactly as an integer, real or
+///  boolean (`port: ${AI_PORT:-11434}` is an integer). Secrets (by variable or key name) always stay strings.
+///  `$${` is a literal `${`.
+///- `file:<path>` (the whole value, after resolving variables) is replaced by the trimmed content of the file.
+///
+///File contents, variables with KEY, TOKEN, SECRET or PASSWORD in their names and variables used as the value of a key
+///with these words in its name (`password: ${DB_PW}`) are secrets.
+///`path` is the name of the node, used to report problems (e.g. `dev`).
+pub fn interpolate_yaml(yaml: &Yaml, path: &str) -> Interpolated {
+    let mut ctx = Context { secrets: Vec::new(), problems: Vec::new() };
+    let yaml = ctx.resolve_node(yaml, path);
+    Interpolated { yaml, secrets: SecretValues::new(ctx.secrets), problems: ctx.problems }
+}
+
+struct Context {
+    secrets: Vec<String>,
+    problems: Vec<InterpolationProblem>,
+}
+
+impl Context {
+    fn add_problem(&mut self, path: &str, message: String) {
+        self.problems.push(InterpolationProblem { path: path.to_owned(), message });
+    }
+
+    fn resolve_node(&mut self, yaml: &Yaml, path: &str) -> Yaml {
+        match yaml {
+            Yaml::String(s) => self.resolve_string(s, path),
+            Yaml::Array(a) => Yaml::Array(a.iter().enumerate().map(|(i, y)| self.resolve_node(y, &format!("{}[{}]", path, i))).collect()),
+            Yaml::Hash(h) => {
+                let mut out = Hash::new();
+                for (k, v) in h {
+                    let key = k.as_str().map(str::to_owned).unwrap_or_else(|| format!("{:?}", k));
+                    out.insert(k.clone(), self.resolve_node(v, &format!("{}.{}", path, key)));
+                }
+                Yaml::Hash(out)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn resolve_string(&mut self, s: &str, path: &str) -> Yaml {
+        let trimmed = s.trim();
+        let only_var = trimmed.starts_with("${") && trimmed.find('}') == Some(trimmed.len() - 1);
+        let Some(resolved) = self.resolve_vars(s, path) else {
+            return Yaml::String(s.to_owned());
+        };
+        if resolved != s && is_secret_name(get_key(path)) {
+            self.secrets.push(resolved.clone());
+        }
+
+        if let Some(file) = resolved.strip_prefix(FILE_REF_PREFIX) {
+            return match fs::read_to_string(file.trim()) {
+                Ok(content) => {
+                    let content = content.trim().to_owned();
+                    self.secrets.push(content.clone());
+                    Yaml::String(content)
+                }
+                Err(e) => {
+                    self.add_problem(path, format!("Unable to read {}. Error: {}", file.trim(), e));
+                    Yaml::String(s.to_owned())
+                }
+            };
+        }
+
+        if only_var && !is_secret_name(get_var_name(trimmed)) && !is_secret_name(get_key(path)) {
+            get_scalar(resolved)
+        } else {
+            Yaml::String(resolved)
+        }
+    }
+
+    ///The string with its variables replaced. None if any variable has no value.
+    fn resolve_vars(&mut self, s: &str, path: &str) -> Option<String> {
+        let mut out = String::new();
+        let mut rest = s;
+        let mut ok = true;
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            if let Some(r) = rest.strip_prefix("$${") {
+                out.push_str("${");
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("${") {
+                let Some(end) = r.find('}') else {
+                    self.add_problem(path, "Missing } in variable reference".to_owned());
+                    return None;
+                };
+                match self.resolve_var(&r[..end], path) {
+                    Some(v) => out.push_str(&v),
+                    None => ok = false,
+                }
+                rest = &r[end + 1..];
+            } else {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+        ok.then_some(out)
+    }
+
+    fn resolve_var(&mut self, expr: &str, path: &str) -> Option<String> {
+        let (name, default) = match expr.split_once(":-") {
+            Some((n, d)) => (n.trim(), Some(d)),
+            None => (expr.trim(), None),
+        };
+        let value = match env::var(name) {
+            Ok(v) if !v.is_empty() => {
+                if is_secret_name(name) {
+                    self.secrets.push(v.clone());
+                }
+                v
+            }
+            _ => match default {
+                Some(d) => d.to_owned(),
+                None => {
+                    self.add_problem(path, format!("Environment variable {} is not set", name));
+                    return None;
+                }
+            },
+        };
+        //The value of a secret key (`password: ${DB_PW}`) is a secret whatever the variable name
+        if is_secret_name(get_key(path)) {
+            self.secrets.push(value.clone());
+        }
+        Some(value)
+    }
+}
+
+///True for names of variables or keys that hold secrets (e.g. `BT_API_KEY`, `password`)
+fn is_secret_name(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    SECRET_VAR_WORDS.iter().any(|w| upper.contains(w))
+}
+
+///Key of a configuration path: its last segment (`dev.auth.password` -> `password`)
+fn get_key(path: &str) -> &str {
+    path.rsplit('.').next().unwrap_or(path)
+}
+
+///Name of the variable of a value that is only `${NAME}` or `${NAME:-default}`
+fn get_var_name(only_var: &str) -> &str {
+    let expr = &only_var[2..only_var.len() - 1];
+    expr.split_once(":-").map_or(expr, |(n, _)| n).trim()
+}
+
+///Integer, real or boolean if `value` is written exactly as one (`8080`, `0.5`, `false`); otherwise the string as is,
+///so values like `007`, `null` or `~` are not changed.
+fn get_scalar(value: String) -> Yaml {
+    let canonical = match Yaml::from_str(&value) {
+        Yaml::Integer(i) if i.to_string() == value => Some(Yaml::Integer(i)),
+        Yaml::Real(r) if r == value => Some(Yaml::Real(r)),
+        Yaml::Boolean(b) if b.to_string() == value => Some(Yaml::Boolean(b)),
+        _ => None,
+    };
+    canonical.unwrap_or(Yaml::String(value))
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_config_interpolation {
+    use yaml_rust2::{Yaml, YamlLoader};
+
+    use super::interpolate_yaml;
+
+    #[test]
+    fn test_interpolate_vars() {
+        //HOME is always set; the BT_TEST_* variables never are
+        let yml = YamlLoader::load_from_str("
+server:
+  host: ${BT_TEST_UNSET_HOST:-ai.example.com}
+  port: ${BT_TEST_UNSET_PORT:-8080}
+  secure: ${BT_TEST_UNSET_SECURE:-false}
+api:
+  path: v1/${BT_TEST_UNSET_PATH:-chat}
+  home: ${HOME}
+  literal: $${HOME} costs $5
+").unwrap();
+        let i = interpolate_yaml(&yml[0], "dev");
+        assert!(i.problems.is_empty());
+        assert_eq!(i.yaml["server"]["host"].as_str().unwrap(), "ai.example.com");
+        assert_eq!(i.yaml["server"]["port"], Yaml::Integer(8080));
+        assert_eq!(i.yaml["server"]["secure"], Yaml::Boolean(false));
+        assert_eq!(i.yaml["api"]["path"].as_str().unwrap(), "v1/chat");
+        assert_eq!(i.yaml["api"]["home"].as_str().unwrap(), std::env::var("HOME").unwrap());
+        assert_eq!(i.yaml["api"]["literal"].as_str().unwrap(), "${HOME} costs $5");
+        assert!(i.secrets.is_empty());
+    }
+
+    #[test]
+    fn test_interpolate_keeps_strings() {
+        let yml = YamlLoader::load_from_str("
+auth:
+  basic:
+    username: ${BT_TEST_UNSET_USER:-007}
+    password: ${BT_TEST_UNSET_PWD:-123456}
+  bearer_token: ${BT_TEST_UNSET_BEARER:-true}
+api:
+  ratio: ${BT_TEST_UNSET_RATIO:-0.5}
+  secret_word: ${BT_TEST_UNSET_WORD:-42}
+  count: ${BT_TEST_UNSET_API_KEY:-42}
+  empty: ${BT_TEST_UNSET_EMPTY:-null}
+  tilde: ${BT_TEST_UNSET_TILDE:-~}
+").unwrap();
+        let i = interpolate_yaml(&yml[0], "dev");
+        assert!(i.problems.is_empty());
+        assert_eq!(i.yaml["auth"]["basic"]["username"].as_str().unwrap(), "007");
+        assert_eq!(i.yaml["auth"]["basic"]["password"].as_str().unwrap(), "123456");
+        assert_eq!(i.yaml["auth"]["bearer_token"].as_str().unwrap(), "true");
+        assert_eq!(i.yaml["api"]["ratio"], Yaml::Real("0.5".to_owned()));
+        assert_eq!(i.yaml["api"]["secret_word"].as_str().unwrap(), "42");
+        assert_eq!(i.yaml["api"]["count"].as_str().unwrap(), "42");
+        assert_eq!(i.yaml["api"]["empty"].as_str().unwrap(), "null");
+        assert_eq!(i.yaml["api"]["tilde"].as_str().unwrap(), "~");
+    }
+
+    #[test]
+    fn test_interpolate_secret_keys() {
+        //HOME is always set and is not a secret by its name
+        let yml = YamlLoader::load_from_str("
+auth:
+  bearer_token: ${HOME}
+  basic:
+    password: x${BT_TEST_UNSET_PW:-pw-default}
+api:
+  home: ${HOME}
+").unwrap();
+        let i = interpolate_yaml(&yml[0], "dev");
+        assert!(i.problems.is_empty());
+        //The whole value of a secret key and the variables in it
+        assert_eq!(i.secrets.len(), 3);
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(i.secrets.redact(&format!("token {} and pw-default in xpw-default", home)), "token *** and *** in ***");
+        assert_eq!(i.yaml["api"]["home"].as_str().unwrap(), home);
+    }
+
+    #[test]
+    fn test_interpolate_problems() {
+        let yml = YamlLoader::load_from_str("
+models:
+  - key: ${BT_TEST_UNSET_API_KEY}
+  - key: file:/nonexistent/bt_test_secret
+").unwrap();
+        let i = interpolate_yaml(&yml[0], "dev");
+        assert_eq!(i.problems.len(), 2);
+        assert_eq!(i.problems[0].to_string(), "dev.models[0].key: Environment variable BT_TEST_UNSET_API_KEY is not set");
+        assert!(i.problems[1].message.starts_with("Unable to read /nonexistent/bt_test_secret"));
+        //Left as written
+        assert_eq!(i.yaml["models"][0]["key"].as_str().unwrap(), "${BT_TEST_UNSET_API_KEY}");
+    }
+
+    #[test]
+    fn test_interpolate_file_secret() {
+        let file = std::env::temp_dir().join(format!("bt_test_secret_{}", std::process::id()));
+        std::fs::write(&file, "s3cr3t-value\n").unwrap();
+        let yml = YamlLoader::load_from_str(&format!("api_key: file:{}\nname: s3cr3t-value-not", file.display())).unwrap();
+        let i = interpolate_yaml(&yml[0], "dev");
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(i.yaml["api_key"].as_str().unwrap(), "s3cr3t-value");
+        assert_eq!(i.secrets.len(), 1);
+        assert_eq!(i.secrets.redact(&format!("{:?}", i.yaml)).matches("***").count(), 2);
+        assert!(!format!("{:?}", i.secrets).contains("s3cr3t"));
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use bt_logger::{log_error, log_info, log_verbose};
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::{ai_config::AIConfig, model_configs::ModelConfigs};
+
+type ConfigLoader<T> = Box<dyn Fn(&Path) -> Result<T, String> + Send + Sync>;
+
+///What happened when a watched configuration file changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReloadEvent {
+    ///The new version was valid and replaced the previous one
+    Reloaded(PathBuf),
+    ///The new version was rejected. The previous one is still in use.
+    Failed { path: PathBuf, message: String },
+}
+
+///Modification time and size of a file, to detect changes without reading it
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn read(path: &Path) -> Option<Self> {
+        fs::metadata(path).ok().map(|m| Self { modified: m.modified().ok(), len: m.len() })
+    }
+}
+
+struct Shared<T> {
+    path: PathBuf,
+    current: RwLock<Arc<T>>,
+    loader: ConfigLoader<T>,
+    stamp: Mutex<Option<FileStamp>>,
+    subscribers: Mutex<Vec<Sender<ReloadEvent>>>,
+}
+
+impl<T> Shared<T> {
+    fn reload(&self) -> Result<(), String> {
+        let event = match (self.loader)(&self.path) {
+            Ok(config) => {
+                *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+                log_info!("reload", "Configuration {} reloaded", self.path.display());
+                ReloadEvent::Reloaded(self.path.clone())
+            },
+            Err(message) => {
+                log_error!("reload", "Configuration {} rejected. The previous version is kept. Error: {}", self.path.display(), &message);
+                ReloadEvent::Failed { path: self.path.clone(), message }
+            },
+        };
+        let result = match &event {
+            ReloadEvent::Reloaded(_) => Ok(()),
+            ReloadEvent::Failed { message, .. } => Err(message.clone()),
+        };
+        //Subscribers that dropped their receiver are removed
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).retain(|s| s.send(event.clone()).is_ok());
+        result
+    }
+
+    fn check_for_changes(&self) -> Option<Result<(), String>> {
+        let stamp = FileStamp::read(&self.path);
+        {
+            let mut last = self.stamp.lock().unwrap_or_else(|e| e.into_inner());
+            if *last == stamp {
+                return None;
+            }
+            //Recorded even if the new version is rejected so it is not reloaded again until it changes
+            *last = stamp;
+        }
+        log_verbose!("check_for_changes", "Configuration {} changed", self.path.display());
+        Some(self.reload())
+    }
+}
+
+///Background thread polling a configuration file. Stopped when dropped.
+struct Watcher {
+    stop: Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+///Configuration that can be reloaded from its file while the application runs.
+///
+///`get` returns a snapshot: a request keeps the version it started with even if the file is reloaded meanwhile.
+///A new version replaces the current one only if it loads and validates; otherwise the current one is kept.
+///Reloading is opt-in: call `reload` or `check_for_changes`, or `watch` to poll the file in the background.
+pub struct ReloadableConfig<T> {
+    shared: Arc<Shared<T>>,
+    watcher: Option<Watcher>,
+}
+
+///AI configuration reloaded from the file in `BT_AI_CONFIGYMLFILE`
+pub type ReloadableAIConfig = ReloadableConfig<AIConfig>;
+///Model configurations reloaded from the file in `BT_LLAMAMODEL_CONFIGYMLFILE`
+pub type ReloadableModelConfigs = ReloadableConfig<ModelConfigs>;
+
+impl<T: Send + Sync + 'static> ReloadableConfig<T> {
+    ///Load the configuration from `path` with `loader`, which must return an error for any version that must not be used
+    pub fn new_with_loader<F>(path: PathBuf, loader: F) -> Result<Self, String>
+    where
+        F: Fn(&Path) -> Result<T, String> + Send + Sync + 'static,
+    {
+        let stamp = FileStamp::read(&path);
+        let config = loader(&path)?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                path,
+                current: RwLock::new(Arc::new(config)),
+                loader: Box::new(loader),
+                stamp: Mutex::new(stamp),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+            watcher: None,
+        })
+    }
+
+    ///Current version of the configuration
+    pub fn get(&self) -> Arc<T> {
+        self.shared.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.shared.path
+    }
+
+    ///Receive a `ReloadEvent` every time the file is reloaded or a new version is rejected
+    pub fn subscribe(&self) -> Receiver<ReloadEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.shared.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(tx);
+        rx
+    }
+
+    ///Load the file now, even if it did not change. Error if the new version was rejected.
+    pub fn reload(&self) -> Result<(), String> {
+        self.shared.reload()
+    }
+
+    ///Reload the file if its modification time or size changed since the last check.
+    ///None if it did not change, otherwise the result of the reload.
+    pub fn check_for_changes(&self) -> Option<Result<(), String>> {
+        self.shared.check_for_changes()
+    }
+
+    ///Check the file for changes every `interval` in a background thread, until `stop_watching` or drop.
+    ///Replaces any previous watch.
+    pub fn watch(&mut self, interval: Duration) {
+        self.stop_watching();
+        let (stop, stop_rx) = mpsc::channel();
+        let shared = self.shared.clone();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let _ = shared.check_for_changes();
+            }
+        });
+        self.watcher = Some(Watcher { stop, handle: Some(handle) });
+    }
+
+    pub fn stop_watching(&mut self) {
+        self.watcher = None;
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+}
+
+impl ReloadableConfig<AIConfig> {
+    ///AI configuration of `run_env`, from the file in `BT_AI_CONFIGYMLFILE` (or the default file)
+    pub fn new_ai_config(run_env: &str) -> Result<Self, String> {
+        Self::new_ai_config_from_file(AIConfig::get_config_file_path(), run_env)
+    }
+
+    ///AI configuration of `run_env` from `path`.
+    ///A version with invalid or duplicated entries, or without platforms, is rejected.
+    pub fn new_ai_config_from_file(path: PathBuf, run_env: &str) -> Result<Self, String> {
+        let run_env = run_env.to_owned();
+        Self::new_with_loader(path, move |p| load_ai_config(p, &run_env))
+    }
+}
+
+impl ReloadableConfig<ModelConfigs> {
+    ///Model configurations of `run_env`, from the file in `BT_LLAMAMODEL_CONFIGYMLFILE` (or the default file)
+    pub fn new_model_configs(run_env: &str) -> Result<Self, String> {
+        Self::new_model_configs_from_file(ModelConfigs::get_config_file_path(), run_env)
+    }
+
+    ///Model configurations of `run_env` from `path`. A version with unresolved values is rejected.
+    pub fn new_model_configs_from_file(path: PathBuf, run_env: &str) -> Result<Self, String> {
+        let run_env = run_env.to_owned();
+        Self::new_with_loader(path, move |p| ModelConfigs::new_from_yaml(&read_yaml_file(p)?, &run_env))
+    }
+}
+
+fn read_yaml_file(path: &Path) -> Result<Yaml, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}. Error: {}", path.display(), e))?;
+    let mut docs = YamlLoader::load_from_str(&text).map_err(|e| format!("Invalid YAML in {}. Error: {}", path.display(), e))?;
+    if docs.is_empty() {
+        return Err(format!("{} is empty", path.display()));
+    }
+    Ok(docs.swap_remove(0))
+}
+
+fn load_ai_config(path: &Path, run_env: &str) -> Result<AIConfig, String> {
+    let ai_config = AIConfig::new_from_yaml(&read_yaml_file(path)?, run_env);
+    let report = ai_config.get_load_report();
+    let mut problems: Vec<String> = report.errors.iter().chain(report.duplicates.iter()).cloned().collect();
+    if ai_config.get_platform_list().is_empty() {
+        problems.push(format!("{}: No platforms defined for this environment", run_env));
+    }
+    if problems.is_empty() {
+        Ok(ai_config)
+    } else {
+        Err(format!("Invalid AI configuration:\n{}", problems.join("\n")))
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_config_reload {
+    use std::{fs, path::PathBuf, thread, time::Duration};
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+
+    use crate::ai_config::AIConfig;
+
+    use super::{ReloadEvent, ReloadableConfig};
+
+    const CONFIG_V1: &str = "
+prod:
+  platform:
+    - name: LOCAL
+      server: {host: localhost, port: 11434, secure: false}
+      api: {ctx_max: 10}
+      models:
+        - model_id: default
+          model: llama3.1:8b
+";
+
+    fn get_default_model(config: &AIConfig) -> String {
+        config.get_model(&"LOCAL".to_owned(), &"default".to_owned(), &String::new())
+    }
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("bt_test_reload_{}_{}.yml", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_reload_ai_config() {
+        build_logger("BACHUETECH", "BT.CONFIG_RELOAD", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let path = write_config("ai", CONFIG_V1);
+        let config = ReloadableConfig::new_ai_config_from_file(path.clone(), "prod").unwrap();
+        let events = config.subscribe();
+        let v1 = config.get();
+        assert_eq!(config.check_for_changes(), None);
+
+        fs::write(&path, CONFIG_V1.replace("llama3.1:8b", "llama3.3:70b").replace("port: 11434", "port: 11435")).unwrap();
+        assert_eq!(config.check_for_changes(), Some(Ok(())));
+        assert_eq!(events.try_recv().unwrap(), ReloadEvent::Reloaded(path.clone()));
+        assert_eq!(get_default_model(&config.get()), "llama3.3:70b");
+        //Snapshots taken before the reload are unchanged
+        assert_eq!(get_default_model(&v1), "llama3.1:8b");
+
+        //Duplicated platform: rejected, the previous version is kept
+        fs::write(&path, format!("{}    - name: LOCAL\n      server: {{host: localhost}}\n", CONFIG_V1)).unwrap();
+        let err = config.check_for_changes().unwrap().unwrap_err();
+        assert!(err.contains("duplicate platform name LOCAL"));
+        assert!(matches!(events.try_recv().unwrap(), ReloadEvent::Failed { .. }));
+        assert_eq!(get_default_model(&config.get()), "llama3.3:70b");
+
+        fs::write(&path, "prod: [unterminated").unwrap();
+        assert!(config.reload().unwrap_err().starts_with("Invalid YAML"));
+        assert_eq!(get_default_model(&config.get()), "llama3.3:70b");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_watch() {
+        build_logger("BACHUETECH", "BT.CONFIG_RELOAD", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let path = write_config("watch", CONFIG_V1);
+        let mut config = ReloadableConfig::new_ai_config_from_file(path.clone(), "prod").unwrap();
+        let events = config.subscribe();
+        config.watch(Duration::from_millis(20));
+        assert!(config.is_watching());
+
+        //Different size so the change is detected even with a coarse modification time
+        fs::write(&path, CONFIG_V1.replace("llama3.1:8b", "llama3.3:70b-instruct")).unwrap();
+        assert_eq!(events.recv_timeout(Duration::from_secs(5)).unwrap(), ReloadEvent::Reloaded(path.clone()));
+        assert_eq!(get_default_model(&config.get()), "llama3.3:70b-instruct");
+
+        config.stop_watching();
+        assert!(!config.is_watching());
+        fs::remove_file(&path).unwrap();
+        thread::sleep(Duration::from_millis(60));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_reload_model_configs() {
+        let path = write_config("model", "prod:\n  models:\n    - model_id: default\n      model_path: ${BT_TEST_UNSET_MODEL_PATH:-qwen3.gguf}\n");
+        let config = ReloadableConfig::new_model_configs_from_file(path.clone(), "prod").unwrap();
+        assert!(config.get().get_model_configs("default").is_some());
+
+        fs::write(&path, "prod:\n  models:\n    - model_id: default\n      model_path: ${BT_TEST_UNSET_MODEL_PATH}\n").unwrap();
+        assert!(config.reload().is_err());
+        assert!(config.get().get_model_configs("default").is_some());
+        fs::remove_file(&path).unwrap();
+
+        assert!(ReloadableConfig::new_model_configs_from_file(path, "prod").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use std::{error::Error, fs, path::Path, time::{SystemTime, UNIX_EPOCH}};
+
+use bt_logger::{get_error, log_verbose, log_warning};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai_chat_helper::{AIChatBodyMessage, AIChatResponse},
+    ai_client::AIClient,
+    ai_tool_loop::{AIToolLoop, AIToolLoopResult},
+    message::{Message, MessageRole},
+};
+
+///A chat session: the history of messages plus the platform, model and system prompt used to continue it.
+///Serializable to JSON to save a session and resume it later.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conversation {
+    id: String,
+    ///Empty for the `default_platform` of the configuration
+    #[serde(default)]
+    platform: String,
+    model_id: String,
+    ///Sent as a SYSTEM message before the history, after the system message of the model configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    messages: Vec<Message>,
+    ///Seconds since the UNIX epoch
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    updated_at: u64,
+}
+
+impl Conversation {
+    pub fn new(id: &str, platform: &str, model_id: &str) -> Self {
+        let now = get_now();
+        Self {
+            id: id.to_owned(),
+            platform: platform.to_owned(),
+            model_id: model_id.to_owned(),
+            system_prompt: None,
+            messages: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    ///Restore a conversation saved with `save`
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let json = fs::read_to_string(path).map_err(|e| get_error!("load", "Error reading conversation file {}. Error: {}", path.display(), e))?;
+        Self::from_json(&json).map_err(|e| get_error!("load", "Invalid conversation file {}. Error: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_json()?).map_err(|e| get_error!("save", "Error writing conversation file {}. Error: {}", path.display(), e))?;
+        log_verbose!("save", "Conversation {} saved to {} ({} messages)", &self.id, path.display(), self.messages.len());
+        Ok(())
+    }
+
+    pub fn get_id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn get_platform(&self) -> &String {
+        &self.platform
+    }
+
+    pub fn get_model_id(&self) -> &String {
+        &self.model_id
+    }
+
+    ///Continue the conversation with another platform or model. The history is kept.
+    pub fn set_model(&mut self, platform: &str, model_id: &str) {
+        self.platform = platform.to_owned();
+        self.model_id = model_id.to_owned();
+        self.touch();
+    }
+
+    pub fn get_system_prompt(&self) -> Option<&String> {
+        self.system_prompt.as_ref()
+    }
+
+    pub fn set_system_prompt(&mut self, system_prompt: Option<String>) {
+        self.system_prompt = system_prompt.filter(|s| !s.trim().is_empty());
+        self.touch();
+    }
+
+    pub fn get_messages(&self) -> &Vec<Message> {
+        &self.messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn get_created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> u64 {
+        self.updated_at
+    }
+
+    pub fn push_message(&mut self, message: Message) {
+        self.messages.push(message);
+        self.touch();
+    }
+
+    pub fn add_user_message(&mut self, content: &str) {
+        self.push_message(Message::new(MessageRole::USER, content.to_owned()));
+    }
+
+    ///Append the message of a model response as is, so `tool_calls` are kept for the TOOL results that answer them
+    pub fn add_response(&mut self, response: &AIChatResponse) {
+        self.push_message(response.message.clone());
+    }
+
+    pub fn add_tool_result(&mut self, content: &str, tool_call_id: Option<String>) {
+        self.push_message(Message::new_tool_result(content.to_owned(), tool_call_id));
+    }
+
+    ///Append the messages of a turn with tool calling (prompt, tool calls, tool results and final answer).
+    ///A last tool call without all its TOOL results is left out: replaying it would be rejected by the platform.
+    pub fn add_tool_loop_result(&mut self, result: &AIToolLoopResult) {
+        let mut messages = result.messages.clone();
+        if let Some(last_call) = messages.iter().rposition(|m| m.has_tool_calls()) {
+            let calls = messages[last_call].get_tools().map_or(0, |t| t.len());
+            let results = messages[last_call + 1..].iter().filter(|m| m.get_role() == MessageRole::TOOL).count();
+            if results < calls {
+                log_warning!("add_tool_loop_result", "Conversation {}: {} tool calls without result left out of the history", &self.id, calls - results);
+                messages.truncate(last_call);
+            }
+        }
+        self.messages.extend(messages);
+        self.touch();
+    }
+
+    ///Messages to send as context of the next request: the system prompt, if any, and the history
+    pub fn get_context(&self) -> Vec<Message> {
+        let mut context: Vec<Message> = self.system_prompt.iter().map(|s| Message::new(MessageRole::SYSTEM, s.clone())).collect();
+        context.extend(self.messages.iter().cloned());
+        context
+    }
+
+    ///Remove the history, keeping the model selection and system prompt
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.touch();
+    }
+
+    ///Send a user message with the history as context. On success the message and the response are appended.
+    pub async fn send(&mut self, client: &AIClient, message: &str) -> Result<AIChatResponse, Box<dyn Error>> {
+        let response = client.chat(&self.platform, &self.model_id, MessageRole::USER, &message.to_owned(), self.get_context()).await?;
+        self.add_user_message(message);
+        self.add_response(&response);
+        Ok(response)
+    }
+
+    ///Same as `send`, executing the tool calls requested by the model (see `AIToolLoop`). All the messages of the turn are appended.
+    pub async fn send_with_tools(&mut self, tool_loop: &AIToolLoop<'_>, message: &str) -> Result<AIChatResponse, Box<dyn Error>> {
+        let result = tool_loop.run(&self.platform, &self.model_id, MessageRole::USER, &message.to_owned(), self.get_context()).await?;
+        self.add_tool_loop_result(&result);
+        Ok(result.response)
+    }
+
+    ///Body of a chat answer: the last message and the rest of the history as context
+    pub fn to_body_message(&self) -> Option<AIChatBodyMessage> {
+        let (message, context) = self.messages.split_last()?;
+        Some(AIChatBodyMessage { message: message.clone(), context: context.to_vec(), done: true })
+    }
+
+    ///Replace the history without changing `updated_at` (used by the stores to rebuild a saved conversation)
+    pub(crate) fn set_messages(&mut self, messages: Vec<Message>) {
+        self.messages = messages;
+    }
+
+    pub(crate) fn set_updated_at(&mut self, updated_at: u64) {
+        self.updated_at = updated_at;
+    }
+
+    ///Same conversation settings (platform, model and system prompt) as `other`, regardless of the history
+    pub(crate) fn has_same_settings(&self, other: &Conversation) -> bool {
+        self.id == other.id && self.platform == other.platform && self.model_id == other.model_id && self.system_prompt == other.system_prompt
+    }
+
+    fn touch(&mut self) {
+        self.updated_at = get_now();
+    }
+}
+
+fn get_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_conversation {
+    use std::{collections::HashMap, env, fs};
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+
+    use crate::{
+        ai_client::{tests_ai_client::{get_mock_config, start_mock_server}, AIClient},
+        ai_tool_loop::{AIToolLoop, AIToolLoopResult},
+        ai_tool_registry::AIToolRegistry,
+        ai_tool_to_call::ToolToCall,
+        ai_tools::AIToolManager,
+        message::{Message, MessageRole},
+    };
+
+    use super::Conversation;
+
+    #[test]
+    fn test_conversation_json() {
+        build_logger("BACHUETECH", "BT.CONVERSATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let mut conv = Conversation::new("c1", "OLLAMALOCAL", "llama3.1");
+        conv.set_system_prompt(Some("Answer in Spanish".to_owned()));
+        conv.add_user_message("2+3?");
+        conv.push_message(Message::new_with_tools(MessageRole::ASSISTANT, "".to_owned(), vec![ToolToCall::new("do_basic_math".to_owned(), HashMap::new())]));
+        conv.add_tool_result("5", Some("call_1".to_owned()));
+
+        let path = env::temp_dir().join(format!("bt_conversation_{}.json", std::process::id()));
+        conv.save(&path).unwrap();
+        let restored = Conversation::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.get_id(), "c1");
+        assert_eq!(restored.get_system_prompt().unwrap(), "Answer in Spanish");
+        assert_eq!(restored.len(), 3);
+        assert!(restored.get_messages()[1].has_tool_calls());
+        assert_eq!(restored.get_messages()[2].get_tool_call_id().unwrap(), "call_1");
+        assert_eq!(restored.get_created_at(), conv.get_created_at());
+
+        let context = restored.get_context();
+        assert_eq!(context.len(), 4);
+        assert_eq!(context[0].get_role().clone(), MessageRole::SYSTEM);
+
+        let minimal = Conversation::from_json("{\"id\":\"c2\",\"model_id\":\"default\"}").unwrap();
+        assert!(minimal.is_empty() && minimal.get_platform().is_empty());
+        assert!(minimal.to_body_message().is_none());
+        assert!(Conversation::load(&env::temp_dir().join("bt_conversation_missing.json")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_send() {
+        build_logger("BACHUETECH", "BT.CONVERSATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let resp = |c: &str| (200, format!("{{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{{\"role\":\"assistant\",\"content\":\"{}\"}},\"done\":true}}", c));
+        let (port, rx) = start_mock_server(vec![resp("Hello!"), resp("Fine"), (500, "{}".to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let mut conv = Conversation::new("c1", "MOCK", "llama3.1");
+        conv.set_system_prompt(Some("Be brief".to_owned()));
+
+        assert_eq!(conv.send(&client, "Hi").await.unwrap().message.get_content(), "Hello!");
+        conv.send(&client, "How are you?").await.unwrap();
+        assert_eq!(conv.len(), 4);
+        rx.recv().unwrap();
+        let second_req = rx.recv().unwrap();
+        assert!(second_req.contains("{\"role\":\"system\",\"content\":\"Be brief\"},{\"role\":\"user\",\"content\":\"Hi\"},{\"role\":\"assistant\",\"content\":\"Hello!\"}"));
+
+        //Failed requests do not change the history
+        assert!(conv.send(&client, "Bye").await.is_err());
+        assert_eq!(conv.len(), 4);
+        let body = conv.to_body_message().unwrap();
+        assert_eq!((body.message.get_content().as_str(), body.context.len()), ("Fine", 3));
+    }
+
+    #[tokio::test]
+    async fn test_conversation_tool_limit() {
+        build_logger("BACHUETECH", "BT.CONVERSATION", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let tool_call = "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"do_basic_math\",\"arguments\":{\"a\":2,\"op\":\"+\",\"b\":3}}}]},\"done\":true}";
+        let answer = "{\"model\":\"llama3.1:8b\",\"created_at\":\"\",\"message\":{\"role\":\"assistant\",\"content\":\"Sorry\"},\"done\":true}";
+        let (port, rx) = start_mock_server(vec![(200, tool_call.to_owned()), (200, answer.to_owned())]);
+        let client = AIClient::new_with_tool_manager(AIToolManager::new_with_config(get_mock_config(port, "ollama")));
+        let mut reg = AIToolRegistry::new();
+        reg.register("do_basic_math", |_| Ok("5".to_owned()));
+        let mut tool_loop = AIToolLoop::new(&client, &reg);
+        tool_loop.set_max_iterations(1);
+
+        let mut conv = Conversation::new("c1", "MOCK", "llama3.1");
+        conv.send_with_tools(&tool_loop, "2+3?").await.unwrap();
+        //user, tool call and its "not executed" result
+        assert_eq!(conv.len(), 3);
+        assert_eq!(conv.get_messages()[2].get_role().clone(), MessageRole::TOOL);
+
+        conv.send(&client, "Go on").await.unwrap();
+        rx.recv().unwrap();
+        let replay = rx.recv().unwrap();
+        assert!(replay.contains("\"tool_calls\":[{\"function\":{\"name\":\"do_basic_math\""));
+        assert!(replay.contains("{\"role\":\"tool\",\"content\":\"Error: not executed. The tool iteration limit was reached\"}"));
+        assert_eq!(conv.len(), 5);
+
+        //A turn ending with unanswered tool calls is not kept
+        let response = serde_json::from_str(tool_call).unwrap();
+        let call = Message::new_with_tools(MessageRole::ASSISTANT, "".to_owned(), vec![ToolToCall::new("do_basic_math".to_owned(), HashMap::new())]);
+        let result = AIToolLoopResult {
+            response,
+            messages: vec![Message::new(MessageRole::USER, "Again".to_owned()), call],
+            iterations: 1,
+            dropped_messages: 0,
+            max_iterations_reached: true,
+        };
+        conv.add_tool_loop_result(&result);
+        assert_eq!(conv.len(), 6);
+        assert_eq!(conv.get_messages()[5].get_content(), "Again");
+    }
+}
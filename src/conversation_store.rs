@@ -0,0 +1,517 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bt_logger::{get_error, log_verbose};
+use serde::{Deserialize, Serialize};
+
+use crate::{conversation::Conversation, message::Message};
+
+const JSON_EXTENSION: &str = "json";
+const JSONL_EXTENSION: &str = "jsonl";
+///Bytes read at a time from the end of a JSONL file
+const TAIL_CHUNK_SIZE: u64 = 8192;
+///FNV-1a offset basis (fingerprint of no messages) and prime
+const FINGERPRINT_START: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+///Storage of conversations by id
+pub trait ConversationStore: Send + Sync {
+    ///Create or replace a conversation
+    fn save(&self, conversation: &Conversation) -> Result<(), Box<dyn Error>>;
+
+    ///None if there is no conversation with this id
+    fn load(&self, id: &str) -> Result<Option<Conversation>, Box<dyn Error>>;
+
+    ///Ids of the stored conversations, sorted
+    fn list(&self) -> Result<Vec<String>, Box<dyn Error>>;
+
+    ///False if there was no conversation with this id
+    fn delete(&self, id: &str) -> Result<bool, Box<dyn Error>>;
+
+    ///Append messages to a stored conversation. An unknown id is an error.
+    fn append_messages(&self, id: &str, messages: &[Message]) -> Result<(), Box<dyn Error>> {
+        let mut conversation = self.load(id)?.ok_or_else(|| get_error!("append_messages", "Conversation {} not found", id))?;
+        for m in messages {
+            conversation.push_message(m.clone());
+        }
+        self.save(&conversation)
+    }
+
+    ///Last `n` messages of a stored conversation, oldest first. An unknown id is an error.
+    fn load_last_messages(&self, id: &str, n: usize) -> Result<Vec<Message>, Box<dyn Error>> {
+        let conversation = self.load(id)?.ok_or_else(|| get_error!("load_last_messages", "Conversation {} not found", id))?;
+        let messages = conversation.get_messages();
+        Ok(messages[messages.len().saturating_sub(n)..].to_vec())
+    }
+}
+
+///Conversations kept in memory. Lost when the process ends.
+#[derive(Default)]
+pub struct MemoryConversationStore {
+    conversations: Mutex<HashMap<String, Conversation>>,
+}
+
+impl MemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Conversation>> {
+        self.conversations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ConversationStore for MemoryConversationStore {
+    fn save(&self, conversation: &Conversation) -> Result<(), Box<dyn Error>> {
+        self.lock().insert(conversation.get_id().clone(), conversation.clone());
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Conversation>, Box<dyn Error>> {
+        Ok(self.lock().get(id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut ids: Vec<String> = self.lock().keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.lock().remove(id).is_some())
+    }
+
+    fn append_messages(&self, id: &str, messages: &[Message]) -> Result<(), Box<dyn Error>> {
+        let mut conversations = self.lock();
+        let conversation = conversations.get_mut(id).ok_or_else(|| get_error!("append_messages", "Conversation {} not found", id))?;
+        for m in messages {
+            conversation.push_message(m.clone());
+        }
+        Ok(())
+    }
+}
+
+///One JSON file per conversation (`<id>.json`, see `Conversation::save`) in a directory.
+///Every save rewrites the whole file.
+pub struct JsonDirConversationStore {
+    dir: PathBuf,
+}
+
+impl JsonDirConversationStore {
+    ///Creates the directory if it does not exist
+    pub fn new(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        create_dir(dir)?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+}
+
+impl ConversationStore for JsonDirConversationStore {
+    fn save(&self, conversation: &Conversation) -> Result<(), Box<dyn Error>> {
+        conversation.save(&get_file_path(&self.dir, conversation.get_id(), JSON_EXTENSION)?)
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Conversation>, Box<dyn Error>> {
+        let path = get_file_path(&self.dir, id, JSON_EXTENSION)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Conversation::load(&path).map(Some)
+    }
+
+    fn list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        list_ids(&self.dir, JSON_EXTENSION)
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        delete_file(&get_file_path(&self.dir, id, JSON_EXTENSION)?)
+    }
+}
+
+///Settings line of a JSONL conversation file: the conversation without messages, the number of messages stored
+///before it and their `fingerprint`, used to check that a saved history still starts with the stored messages.
+#[derive(Serialize, Deserialize)]
+struct JsonlSettings {
+    #[serde(flatten)]
+    conversation: Conversation,
+    message_count: usize,
+    fingerprint: String,
+}
+
+///Line of a JSONL conversation file: conversation settings or a message
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum JsonlRecord {
+    Conversation(JsonlSettings),
+    Message(Message),
+}
+
+///One append-only JSONL file per conversation (`<id>.jsonl`) in a directory.
+///Every write appends the new messages and a settings line with the message count, so `save` and `append_messages`
+///only read the end of the file, as `load_last_messages` does. The last settings line wins.
+///A conversation whose history no longer starts with the stored messages (e.g. after `Conversation::clear`)
+///is rewritten to a temporary file that then replaces the stored one.
+pub struct JsonlConversationStore {
+    dir: PathBuf,
+}
+
+impl JsonlConversationStore {
+    ///Creates the directory if it does not exist
+    pub fn new(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        create_dir(dir)?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    ///Read a whole conversation file: the last settings line and all the messages
+    fn read_file(path: &Path) -> Result<Option<Conversation>, Box<dyn Error>> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(get_error!("read_file", "Error opening conversation file {}. Error: {}", path.display(), e).into()),
+        };
+
+        let mut conversation: Option<Conversation> = None;
+        let mut messages: Vec<Message> = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| get_error!("read_file", "Error reading conversation file {}. Error: {}", path.display(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_record(line.as_bytes(), path, i + 1)? {
+                JsonlRecord::Conversation(s) => conversation = Some(s.conversation),
+                JsonlRecord::Message(m) => messages.push(m),
+            }
+        }
+
+        let mut conversation = conversation.ok_or_else(|| get_error!("read_file", "Conversation file {} has no conversation line", path.display()))?;
+        conversation.set_messages(messages);
+        Ok(Some(conversation))
+    }
+
+    ///Visit the records of a file from the last one, reading it from the end, until `visit` returns false.
+    ///Error if the file does not exist.
+    fn read_backwards(path: &Path, mut visit: impl FnMut(JsonlRecord) -> bool) -> Result<(), Box<dyn Error>> {
+        let mut file = File::open(path).map_err(|e| get_error!("read_backwards", "Error opening conversation file {}. Error: {}", path.display(), e))?;
+        let mut pos = file.seek(SeekFrom::End(0))?;
+        //Start of the line that continues in the chunks already read
+        let mut partial: Vec<u8> = Vec::new();
+        while pos > 0 {
+            let size = TAIL_CHUNK_SIZE.min(pos);
+            pos -= size;
+            file.seek(SeekFrom::Start(pos))?;
+            let mut chunk = vec![0; size as usize];
+            file.read_exact(&mut chunk)?;
+            chunk.extend_from_slice(&partial);
+            partial = chunk;
+
+            //The first line is complete only at the start of the file
+            let first_complete = if pos == 0 { 0 } else {
+                match partial.iter().position(|b| *b == b'\n') {
+                    Some(i) => i + 1,
+                    None => continue,
+                }
+            };
+            let lines = partial.split_off(first_complete);
+            for line in lines.split(|b| *b == b'\n').rev().filter(|l| !l.trim_ascii().is_empty()) {
+                if !visit(parse_record(line, path, 0)?) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Settings line at the end of the file. None if the file does not exist or does not end with one (interrupted write).
+    fn read_last_settings(path: &Path) -> Result<Option<JsonlSettings>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut last = None;
+        Self::read_backwards(path, |r| {
+            if let JsonlRecord::Conversation(s) = r {
+                last = Some(s);
+            }
+            false
+        })?;
+        Ok(last)
+    }
+
+    fn get_settings(conversation: &Conversation, message_count: usize, fingerprint: u64) -> JsonlRecord {
+        let mut settings = conversation.clone();
+        settings.set_messages(Vec::new());
+        JsonlRecord::Conversation(JsonlSettings { conversation: settings, message_count, fingerprint: format!("{:016x}", fingerprint) })
+    }
+
+    fn get_lines(records: &[JsonlRecord]) -> Result<String, Box<dyn Error>> {
+        let mut lines = String::new();
+        for r in records {
+            lines.push_str(&serde_json::to_string(r)?);
+            lines.push('\n');
+        }
+        Ok(lines)
+    }
+
+    fn append_lines(path: &Path, records: &[JsonlRecord]) -> Result<(), Box<dyn Error>> {
+        let lines = Self::get_lines(records)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| get_error!("append_lines", "Error opening conversation file {}. Error: {}", path.display(), e))?;
+        file.write_all(lines.as_bytes()).map_err(|e| get_error!("append_lines", "Error writing conversation file {}. Error: {}", path.display(), e).into())
+    }
+
+    ///Write a whole conversation to a temporary file and replace the stored file with it
+    fn rewrite(path: &Path, conversation: &Conversation) -> Result<(), Box<dyn Error>> {
+        let messages = conversation.get_messages();
+        let mut records: Vec<JsonlRecord> = messages.iter().cloned().map(JsonlRecord::Message).collect();
+        records.push(Self::get_settings(conversation, messages.len(), get_fingerprint(FINGERPRINT_START, messages)));
+        let tmp_path = path.with_extension(format!("{}.tmp", JSONL_EXTENSION));
+        fs::write(&tmp_path, Self::get_lines(&records)?).map_err(|e| get_error!("rewrite", "Error writing conversation file {}. Error: {}", tmp_path.display(), e))?;
+        fs::rename(&tmp_path, path).map_err(|e| get_error!("rewrite", "Error replacing conversation file {}. Error: {}", path.display(), e).into())
+    }
+}
+
+impl ConversationStore for JsonlConversationStore {
+    fn save(&self, conversation: &Conversation) -> Result<(), Box<dyn Error>> {
+        let path = get_file_path(&self.dir, conversation.get_id(), JSONL_EXTENSION)?;
+        let messages = conversation.get_messages();
+        let stored = Self::read_last_settings(&path)?.filter(|s| {
+            s.message_count <= messages.len() && s.fingerprint == format!("{:016x}", get_fingerprint(FINGERPRINT_START, &messages[..s.message_count]))
+        });
+        let Some(stored) = stored else {
+            if path.exists() {
+                log_verbose!("save", "Conversation {} does not start with the stored messages. Rewriting {}", conversation.get_id(), path.display());
+            }
+            return Self::rewrite(&path, conversation);
+        };
+
+        let new_messages = &messages[stored.message_count..];
+        if new_messages.is_empty() && stored.conversation.has_same_settings(conversation) {
+            return Ok(());
+        }
+        let fingerprint = u64::from_str_radix(&stored.fingerprint, 16)?;
+        let mut records: Vec<JsonlRecord> = new_messages.iter().cloned().map(JsonlRecord::Message).collect();
+        records.push(Self::get_settings(conversation, messages.len(), get_fingerprint(fingerprint, new_messages)));
+        Self::append_lines(&path, &records)
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Conversation>, Box<dyn Error>> {
+        Self::read_file(&get_file_path(&self.dir, id, JSONL_EXTENSION)?)
+    }
+
+    fn list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        list_ids(&self.dir, JSONL_EXTENSION)
+    }
+
+    fn delete(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        delete_file(&get_file_path(&self.dir, id, JSONL_EXTENSION)?)
+    }
+
+    fn append_messages(&self, id: &str, messages: &[Message]) -> Result<(), Box<dyn Error>> {
+        let path = get_file_path(&self.dir, id, JSONL_EXTENSION)?;
+        let Some(stored) = Self::read_last_settings(&path)? else {
+            //Missing file, or messages after the last settings line: only a full load and save can fix it
+            let mut conversation = Self::read_file(&path)?.ok_or_else(|| get_error!("append_messages", "Conversation {} not found", id))?;
+            for m in messages {
+                conversation.push_message(m.clone());
+            }
+            return Self::rewrite(&path, &conversation);
+        };
+
+        let mut conversation = stored.conversation;
+        conversation.set_updated_at(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+        let fingerprint = get_fingerprint(u64::from_str_radix(&stored.fingerprint, 16)?, messages);
+        let mut records: Vec<JsonlRecord> = messages.iter().cloned().map(JsonlRecord::Message).collect();
+        records.push(Self::get_settings(&conversation, stored.message_count + messages.len(), fingerprint));
+        Self::append_lines(&path, &records)
+    }
+
+    fn load_last_messages(&self, id: &str, n: usize) -> Result<Vec<Message>, Box<dyn Error>> {
+        let path = get_file_path(&self.dir, id, JSONL_EXTENSION)?;
+        if !path.exists() {
+            return Err(get_error!("load_last_messages", "Conversation {} not found", id).into());
+        }
+        let mut messages: Vec<Message> = Vec::new();
+        if n > 0 {
+            Self::read_backwards(&path, |r| {
+                if let JsonlRecord::Message(m) = r {
+                    messages.push(m);
+                }
+                messages.len() < n
+            })?;
+        }
+        messages.reverse();
+        Ok(messages)
+    }
+}
+
+///Fingerprint (FNV-1a) of the JSON of `messages`, continuing from `start`. Stable across builds, as it is stored in the files.
+fn get_fingerprint(start: u64, messages: &[Message]) -> u64 {
+    let mut hash = start;
+    for m in messages {
+        for b in serde_json::to_vec(m).unwrap_or_default().into_iter().chain([b'\n']) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+///Parse a line of a JSONL conversation file. `line_number` is only used in the error (0 if unknown).
+fn parse_record(line: &[u8], path: &Path, line_number: usize) -> Result<JsonlRecord, Box<dyn Error>> {
+    serde_json::from_slice(line).map_err(|e| get_error!("parse_record", "Invalid line {} of conversation file {}. Error: {}", line_number, path.display(), e).into())
+}
+
+fn create_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir).map_err(|e| get_error!("create_dir", "Error creating conversation directory {}. Error: {}", dir.display(), e).into())
+}
+
+///File of a conversation. Ids are file names: letters, digits, `-`, `_` and `.`, not starting with `.`.
+fn get_file_path(dir: &Path, id: &str, extension: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let valid = !id.is_empty() && !id.starts_with('.') && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(get_error!("get_file_path", "Invalid conversation id '{}'. Use letters, digits, '-', '_' and '.'", id).into());
+    }
+    Ok(dir.join(format!("{}.{}", id, extension)))
+}
+
+fn list_ids(dir: &Path, extension: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let entries = fs::read_dir(dir).map_err(|e| get_error!("list_ids", "Error reading conversation directory {}. Error: {}", dir.display(), e))?;
+    let mut ids: Vec<String> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == extension))
+        .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(str::to_owned))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+fn delete_file(path: &Path) -> Result<bool, Box<dyn Error>> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(get_error!("delete_file", "Error deleting conversation file {}. Error: {}", path.display(), e).into()),
+    }
+}
+
+//**********/
+//UNIT TEST
+//*********/
+#[cfg(test)]
+mod tests_conversation_store {
+    use std::{env, fs, path::PathBuf};
+
+    use bt_logger::{build_logger, LogLevel, LogTarget};
+
+    use crate::{conversation::Conversation, message::{Message, MessageRole}};
+
+    use super::{ConversationStore, JsonDirConversationStore, JsonlConversationStore, MemoryConversationStore, TAIL_CHUNK_SIZE};
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("bt_conversation_store_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn get_conversation(id: &str, len: usize) -> Conversation {
+        let mut conv = Conversation::new(id, "OLLAMALOCAL", "llama3.1");
+        for i in 0..len {
+            conv.add_user_message(&format!("m{}", i));
+        }
+        conv
+    }
+
+    fn get_contents(messages: &[Message]) -> Vec<String> {
+        messages.iter().map(|m| m.get_content().clone()).collect()
+    }
+
+    ///Behavior shared by all the stores
+    fn check_store(store: &dyn ConversationStore) {
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.load("c1").unwrap().is_none());
+
+        let mut conv = get_conversation("c1", 3);
+        store.save(&conv).unwrap();
+        store.save(&get_conversation("c0", 1)).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["c0", "c1"]);
+
+        conv.add_user_message("m3");
+        conv.set_system_prompt(Some("Be brief".to_owned()));
+        store.save(&conv).unwrap();
+        store.append_messages("c1", &[Message::new(MessageRole::ASSISTANT, "m4".to_owned())]).unwrap();
+        let loaded = store.load("c1").unwrap().unwrap();
+        assert_eq!(get_contents(loaded.get_messages()), vec!["m0", "m1", "m2", "m3", "m4"]);
+        assert_eq!(loaded.get_system_prompt().unwrap(), "Be brief");
+        assert_eq!(get_contents(&store.load_last_messages("c1", 2).unwrap()), vec!["m3", "m4"]);
+        assert_eq!(store.load_last_messages("c1", 10).unwrap().len(), 5);
+        assert!(store.load_last_messages("c1", 0).unwrap().is_empty());
+        assert!(store.append_messages("unknown", &[]).is_err());
+        assert!(store.load_last_messages("unknown", 1).is_err());
+
+        conv.clear();
+        conv.add_user_message("new");
+        store.save(&conv).unwrap();
+        assert_eq!(get_contents(store.load("c1").unwrap().unwrap().get_messages()), vec!["new"]);
+
+        //A different history as long as or longer than the stored one replaces it
+        conv.clear();
+        for m in ["n0", "n1", "n2", "n3"] {
+            conv.add_user_message(m);
+        }
+        store.save(&conv).unwrap();
+        assert_eq!(get_contents(store.load("c1").unwrap().unwrap().get_messages()), vec!["n0", "n1", "n2", "n3"]);
+
+        assert!(store.delete("c0").unwrap());
+        assert!(!store.delete("c0").unwrap());
+        assert_eq!(store.list().unwrap(), vec!["c1"]);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        build_logger("BACHUETECH", "BT.CONVERSATION_STORE", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        check_store(&MemoryConversationStore::new());
+    }
+
+    #[test]
+    fn test_json_dir_store() {
+        build_logger("BACHUETECH", "BT.CONVERSATION_STORE", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let dir = get_test_dir("json");
+        let store = JsonDirConversationStore::new(&dir).unwrap();
+        check_store(&store);
+        assert!(store.save(&get_conversation("../c2", 1)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_jsonl_store() {
+        build_logger("BACHUETECH", "BT.CONVERSATION_STORE", LogLevel::VERBOSE, LogTarget::STD_ERROR, None);
+        let dir = get_test_dir("jsonl");
+        let store = JsonlConversationStore::new(&dir).unwrap();
+        check_store(&store);
+
+        //Saving again only appends the new messages
+        let mut conv = get_conversation("c2", 2);
+        store.save(&conv).unwrap();
+        conv.add_user_message("m2");
+        store.save(&conv).unwrap();
+        store.save(&conv).unwrap();
+        let lines = fs::read_to_string(dir.join("c2.jsonl")).unwrap();
+        assert_eq!(lines.lines().count(), 5);
+        assert!(lines.lines().last().unwrap().starts_with("{\"record\":\"conversation\",\"id\":\"c2\""));
+        assert!(lines.lines().last().unwrap().contains("\"message_count\":3"));
+        assert!(!dir.join("c2.jsonl.tmp").exists());
+
+        //Messages spread over several chunks read from the end of the file
+        let long = "x".repeat(TAIL_CHUNK_SIZE as usize / 3);
+        let msgs: Vec<Message> = (0..10).map(|i| Message::new(MessageRole::USER, format!("{}{}", i, long))).collect();
+        store.append_messages("c2", &msgs).unwrap();
+        let last = store.load_last_messages("c2", 4).unwrap();
+        assert_eq!(last.iter().map(|m| m.get_content().chars().next().unwrap()).collect::<String>(), "6789");
+        assert_eq!(get_contents(&store.load_last_messages("c2", 13).unwrap()[..3]), vec!["m0", "m1", "m2"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,31 @@
+//Allows the code generated by bt_ai_core_derive (::bt_ai_core::...) to be used inside this crate
+extern crate self as bt_ai_core;
+
 pub mod ai_config;
+pub mod ai_config_def;
+pub mod ai_config_merge;
+pub mod ai_config_validation;
+pub mod config_interpolation;
+pub mod config_reload;
 pub mod message;
 pub mod ai_tools;
+pub mod ai_tool_validation;
+pub mod ai_tool_schema;
 pub mod ai_tool_to_call;
+pub mod ai_context;
+pub mod ai_token_budget;
+pub mod ai_summarizer;
 pub mod ai_chat_helper;
+pub mod ai_endpoint_helper;
+pub mod ai_openai_helper;
 pub mod ai_stream_helper;
+pub mod ai_client;
+pub mod ai_tool_registry;
+pub mod ai_tool_loop;
+pub mod conversation;
+pub mod conversation_store;
 pub mod model_configs;
-pub mod parameter_names;
\ No newline at end of file
+pub mod parameter_names;
+
+#[cfg(feature = "derive")]
+pub use bt_ai_core_derive::AiTool;
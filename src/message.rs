@@ -38,6 +38,9 @@ pub struct Message{
     content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<ToolToCall>>,
+    ///Id of the tool call this TOOL message answers (Required by OpenAI compatible platforms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
 }
 
 impl Message{
@@ -46,6 +49,7 @@ impl Message{
             role,
             content: msg_content,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -54,6 +58,17 @@ impl Message{
             role,
             content: msg_content,
             tool_calls: Some(tools),
+            tool_call_id: None,
+        }
+    }
+
+    ///TOOL message with the result of a tool call. `tool_call_id` is the id of the `ToolToCall` being answered, if any.
+    pub fn new_tool_result(msg_content: String, tool_call_id: Option<String>) -> Self{
+        Message{
+            role: MessageRole::TOOL,
+            content: msg_content,
+            tool_calls: None,
+            tool_call_id,
         }
     }
 
@@ -76,6 +91,15 @@ impl Message{
     pub fn get_tools(&self) -> Option<Vec<ToolToCall>> {
         self.tool_calls.clone()
     }
+
+    pub fn get_tool_call_id(&self) -> Option<&String> {
+        self.tool_call_id.as_ref()
+    }
+
+    ///True for an assistant message that requests tool calls
+    pub fn has_tool_calls(&self) -> bool {
+        self.tool_calls.as_ref().is_some_and(|t| !t.is_empty())
+    }
 }
 
 //**********/
@@ -111,6 +135,7 @@ mod tests_message{
             role: MessageRole::USER,
             content: ctt.clone(),
             tool_calls: Some(vec![ttc]),
+            tool_call_id: None,
         };
 
         assert_eq!(msg.get_role().clone(),MessageRole::USER);
@@ -118,6 +143,14 @@ mod tests_message{
         assert_eq!(msg.get_tools().unwrap()[0].get_function_name(),"FunctName");
     }
 
+    #[test]
+    fn test_message_tool_result(){
+        let msg = Message::new_tool_result("42".to_owned(), Some("call_1".to_owned()));
+        assert_eq!(msg.get_role().clone(),MessageRole::TOOL);
+        assert_eq!(msg.get_tool_call_id().unwrap(),"call_1");
+        assert_eq!(serde_json::to_string(&msg).unwrap(),"{\"role\":\"tool\",\"content\":\"42\",\"tool_call_id\":\"call_1\"}");
+    }
+
     #[test]
     fn test_as_str() {
         assert_eq!(MessageRole::USER.as_str(), "user");
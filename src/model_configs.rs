@@ -1,4 +1,4 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, env, fmt, path::PathBuf};
 
 use bt_logger::get_error;
 use bt_string_utils::{remove_char, RemoveLocationEnum};
@@ -6,9 +6,9 @@ use bt_yaml_utils::{get_f32, get_u32, get_usize, get_yaml};
 use rand::Rng;
 use yaml_rust2::Yaml;
 
-use crate::{ai_config::SupportedFunctions, parameter_names::{FRAMEWORK_MODEL_DISABLE_GPU, SAMPLER_PENALTY_LAST_N, SAMPLER_PENALTY_REPEAT, SAMPLER_SEED, SAMPLER_TEMP, SAMPLER_TOP_K, SAMPLER_TOP_P}};
+use crate::{ai_config::SupportedFunctions, config_interpolation::{interpolate_yaml, SecretValues}, parameter_names::{FRAMEWORK_MODEL_DISABLE_GPU, SAMPLER_PENALTY_LAST_N, SAMPLER_PENALTY_REPEAT, SAMPLER_SEED, SAMPLER_TEMP, SAMPLER_TOP_K, SAMPLER_TOP_P}};
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct ModelConfig{
     model_root_folder: String,
     model_path: String, /// The path to the model
@@ -18,9 +18,31 @@ pub struct ModelConfig{
     model_params: HashMap<String,Yaml>,
     sampler_params: HashMap<String,Yaml>,
     model_cfg_parms: HashMap<String,String>,
+    secrets: SecretValues,
 }
 
-#[derive(Clone)]
+/// Secret values resolved from environment variables or `file:` references are redacted
+impl fmt::Debug for ModelConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let redact = |v: &dyn fmt::Debug| {
+            let text = if alternate { format!("{:#?}", v) } else { format!("{:?}", v) };
+            self.secrets.redact(&text)
+        };
+        f.debug_struct("ModelConfig")
+            .field("model_root_folder", &self.model_root_folder)
+            .field("model_path", &self.model_path)
+            .field("system", &format_args!("{}", redact(&self.system)))
+            .field("tools", &self.tools)
+            .field("ctx_params", &format_args!("{}", redact(&self.ctx_params)))
+            .field("model_params", &format_args!("{}", redact(&self.model_params)))
+            .field("sampler_params", &format_args!("{}", redact(&self.sampler_params)))
+            .field("model_cfg_parms", &format_args!("{}", redact(&self.model_cfg_parms)))
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct ModelConfigs{
     models: HashMap<String, ModelConfig>,
 }
@@ -35,11 +57,28 @@ impl ModelConfigs{
         let llama_model_cfg = 
                                 get_yaml(LLAMA_MODEL_YML_CONFIG_ENV_VAR_NAME,LLAMA_MODEL_YML_CONFIG)
                                 .map_err(|e| get_error!("new","Error reading Model Configuation File. Error {}",e))?;
+        Self::new_from_yaml(&llama_model_cfg, run_env)
+    }
+
+    /// Path of the model YML config file: `BT_LLAMAMODEL_CONFIGYMLFILE` if set, otherwise `config/model-cfg.yml`
+    pub fn get_config_file_path() -> PathBuf {
+        PathBuf::from(env::var(LLAMA_MODEL_YML_CONFIG_ENV_VAR_NAME).unwrap_or(LLAMA_MODEL_YML_CONFIG.to_owned()))
+    }
+
+    /// Build the model configurations for `run_env` from an already loaded YAML document.
+    /// `${VAR}`, `${VAR:-default}` and `file:` references are resolved; any of them without value is an error.
+    pub fn new_from_yaml(llama_model_cfg: &Yaml, run_env: &str) -> Result<Self, String> {
+        let env_cfg = interpolate_yaml(&llama_model_cfg[run_env], run_env);
+        if !env_cfg.problems.is_empty() {
+            let details: Vec<String> = env_cfg.problems.iter().map(|p| p.to_string()).collect();
+            return Err(get_error!("new_from_yaml","Unable to resolve Model Configuation values:\n{}",details.join("\n")));
+        }
+        let env_yaml = env_cfg.yaml;
         let root_folder = remove_char(RemoveLocationEnum::End, 
-                                            &llama_model_cfg[run_env]["root_folder"].as_str().unwrap_or(DEFAULT_ROOT_MODEL_FOLDER).to_owned(),
+                                            &env_yaml["root_folder"].as_str().unwrap_or(DEFAULT_ROOT_MODEL_FOLDER).to_owned(),
                                             '/');
         let mut models: HashMap<String, ModelConfig> = HashMap::new();
-        let model_list = llama_model_cfg[run_env]["models"].clone();
+        let model_list = env_yaml["models"].clone();
         for m in model_list {
             let mut v_ctx_params: HashMap<String, Yaml> = HashMap::new();
             let ctx_param_list = m["ctx_params"].clone();
@@ -69,6 +108,7 @@ impl ModelConfigs{
                 model_params: v_model_params,
                 sampler_params: v_sampler_params,
                 model_cfg_parms: HashMap::new(),
+                secrets: env_cfg.secrets.clone(),
             });
         }
 
@@ -175,6 +215,8 @@ impl ModelConfig {
 #[cfg(test)]
 mod model_config_tests {
     use bt_logger::{build_logger, log_verbose, LogLevel, LogTarget};
+    use yaml_rust2::YamlLoader;
+
     use super::ModelConfigs;
 
     #[cfg(test)]
@@ -253,4 +295,31 @@ mod model_config_tests {
         log_verbose!("test_sampler_ctx_param", "temp {:?}", p);
         assert_eq!(p.as_f64().unwrap(), 1.0);
     }       
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_model_cfg_interpolation(){
+        build_logger("BACHUETECH","SERVER_CONFIG",LogLevel::VERBOSE,LogTarget::STD_ERROR,None);
+        let secret_file = std::env::temp_dir().join(format!("bt_test_hf_token_{}", std::process::id()));
+        std::fs::write(&secret_file, "hf_s3cr3t").unwrap();
+        let yml = YamlLoader::load_from_str(&format!("
+dev:
+  root_folder: ${{BT_TEST_UNSET_MODELS:-/models}}/
+  models:
+    - model_id: qwen3
+      model_path: qwen3.gguf
+      model_params:
+        - param_id: hf_token
+          param_value: file:{}
+", secret_file.display())).unwrap();
+        let mc = ModelConfigs::new_from_yaml(&yml[0], VALID_ENV).unwrap();
+        std::fs::remove_file(&secret_file).unwrap();
+
+        let c = mc.get_model_configs("qwen3").unwrap();
+        assert_eq!(c.get_model_file_path().to_str().unwrap(), "/models/qwen3.gguf");
+        assert_eq!(c.get_model_param("hf_token").unwrap().as_str().unwrap(), "hf_s3cr3t");
+        assert!(!format!("{:?}", c).contains("hf_s3cr3t"));
+
+        let yml = YamlLoader::load_from_str("dev:\n  root_folder: ${BT_TEST_UNSET_MODELS}").unwrap();
+        assert!(ModelConfigs::new_from_yaml(&yml[0], VALID_ENV).unwrap_err().contains("BT_TEST_UNSET_MODELS is not set"));
+    }
+}